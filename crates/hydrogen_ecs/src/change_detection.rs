@@ -0,0 +1,94 @@
+use std::marker::PhantomData;
+
+use derive_more::*;
+use serde::{Deserialize, Serialize};
+
+use crate::component::{ComponentId, ComponentType};
+
+/// A point in a [`World`](crate::world::World)'s history. Every time a component is inserted or mutably accessed, it
+/// is stamped with the world's current tick.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    From,
+    Into,
+)]
+pub struct Tick(pub u32);
+
+/// The ticks at which a component was added to its entity and last changed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: Tick,
+    pub changed: Tick,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: Tick) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    pub fn is_added(&self, since: Tick) -> bool {
+        self.added > since
+    }
+
+    pub fn is_changed(&self, since: Tick) -> bool {
+        self.changed > since
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IsVariant)]
+pub enum QueryFilter {
+    /// Only match entities whose component was added after the given tick.
+    Added(ComponentId),
+    /// Only match entities whose component was added or changed after the given tick.
+    Changed(ComponentId),
+}
+
+impl QueryFilter {
+    pub fn component_id(&self) -> ComponentId {
+        match self {
+            Self::Added(component_id) => *component_id,
+            Self::Changed(component_id) => *component_id,
+        }
+    }
+
+    pub fn matches(&self, ticks: ComponentTicks, since: Tick) -> bool {
+        match self {
+            Self::Added(_) => ticks.is_added(since),
+            Self::Changed(_) => ticks.is_changed(since),
+        }
+    }
+}
+
+/// A type that can be used as a filter in [`query_filtered`](crate::world::query_filtered) and friends.
+pub trait ChangeFilter {
+    const FILTER: QueryFilter;
+}
+
+/// Filters a query to entities whose `T` component was added since the given tick.
+#[derive(Debug)]
+pub struct Added<T>(PhantomData<T>);
+
+impl<T: ComponentType> ChangeFilter for Added<T> {
+    const FILTER: QueryFilter = QueryFilter::Added(T::COMPONENT_ID);
+}
+
+/// Filters a query to entities whose `T` component was added or changed since the given tick.
+#[derive(Debug)]
+pub struct Changed<T>(PhantomData<T>);
+
+impl<T: ComponentType> ChangeFilter for Changed<T> {
+    const FILTER: QueryFilter = QueryFilter::Changed(T::COMPONENT_ID);
+}
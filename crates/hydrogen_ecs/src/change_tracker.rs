@@ -1,27 +1,71 @@
-use std::{collections::BTreeMap, sync::Mutex};
+use std::{collections::BTreeMap, mem, sync::Mutex};
 
-use hydrogen_core::events::EventSender;
+use derive_more::IsVariant;
+use hydrogen_core::events::{EventReceiver, EventSender};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    component::{ComponentId, SerializableComponent},
+    change_detection::Tick,
+    component::{Component, ComponentId, SerializableComponent},
     entity::EntityId,
     world::World,
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(bound(
+    serialize = "Box<T>: Serialize",
+    deserialize = "Box<T>: Deserialize<'de>"
+))]
 pub enum ComponentTrackerEvent<T: ?Sized = dyn SerializableComponent> {
     Added(Box<T>),
     Changed { old: Box<T>, new: Box<T> },
     Removed(Box<T>),
 }
 
+// derived `Clone` would require `T: Clone`, which trait objects can't satisfy
+impl<T: ?Sized> Clone for ComponentTrackerEvent<T>
+where
+    Box<T>: Clone,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Self::Added(new) => Self::Added(new.clone()),
+            Self::Changed { old, new } => Self::Changed {
+                old: old.clone(),
+                new: new.clone(),
+            },
+            Self::Removed(old) => Self::Removed(old.clone()),
+        }
+    }
+}
+
+/// What happened to a tracked component, without its values, so that it can be sent for components of every type.
+/// See [`World::component_changes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IsVariant)]
+pub enum ComponentChange {
+    Added,
+    Changed,
+    Removed,
+}
+
+/// Follows one component of one entity using its [change ticks](crate::change_detection), which are set whenever it's
+/// inserted or mutably accessed, and the removals the world reports with [`Self::on_removed`].
 #[derive(Debug, Clone)]
 pub struct EntityComponentTracker {
     pub entity_id: EntityId,
     pub component_id: ComponentId,
+    /// Only kept while something receives [`ComponentTrackerEvent`]s, which need it for their `old` value.
     pub previous_value: Option<Box<dyn SerializableComponent>>,
+    /// The world's change tick as of the last update. Components that haven't been touched since then aren't changed.
+    pub last_checked: Tick,
     pub initialized: bool,
+    /// Whether the component existed at the last update.
+    present: bool,
+    /// Set when the component is removed, with its value if it's serializable and needed.
+    removal: Option<Option<Box<dyn SerializableComponent>>>,
+    /// Whether the component has ever been removed, after which a missing entity is taken to be deleted rather than
+    /// not spawned yet.
+    has_been_removed: bool,
 }
 
 impl EntityComponentTracker {
@@ -30,45 +74,119 @@ impl EntityComponentTracker {
             entity_id,
             component_id,
             previous_value: None,
+            last_checked: Tick::default(),
             initialized: false,
+            present: false,
+            removal: None,
+            has_been_removed: false,
         }
     }
 
-    pub fn update(&mut self, ecs_world: &World) -> Option<ComponentTrackerEvent> {
-        let initialized = self.initialized;
-        self.initialized = true;
+    /// Remembers that the component was removed, since its ticks go with it.
+    pub fn on_removed(&mut self, component: &dyn Component, keep_value: bool) {
+        self.has_been_removed = true;
+        self.removal = Some(
+            keep_value
+                .then(|| component.as_serializable().map(|c| c.clone_box()))
+                .flatten(),
+        );
+    }
+
+    /// Returns what happened to the component since the last update, along with the matching
+    /// [`ComponentTrackerEvent`]s if `with_values` is set and the component is serializable. A component that was
+    /// removed and added again is both [`ComponentChange::Removed`] and [`ComponentChange::Added`].
+    ///
+    /// The world's change tick must be advanced after every update, so that later changes can be told apart.
+    pub fn update(
+        &mut self,
+        ecs_world: &World,
+        with_values: bool,
+    ) -> Vec<(ComponentChange, Option<ComponentTrackerEvent>)> {
+        let initialized = mem::replace(&mut self.initialized, true);
+        let last_checked = mem::replace(&mut self.last_checked, ecs_world.change_tick());
+        let ticks = ecs_world.component_ticks(self.entity_id, self.component_id);
+
+        let mut changes = Vec::new();
+        let mut was_present = self.present;
+        if let Some(removed_value) = self.removal.take()
+            && was_present
+        {
+            was_present = false;
+            let old = removed_value.or_else(|| self.previous_value.take());
+            changes.push((
+                ComponentChange::Removed,
+                old.map(ComponentTrackerEvent::Removed),
+            ));
+        }
+        self.present = ticks.is_some();
 
         let current_value = ecs_world
             .get_component(self.entity_id, self.component_id)
-            .and_then(|c| c.as_serializable());
-        let previous_value = self.previous_value.as_deref();
-
-        let (new_previous_value, event) = match (previous_value, current_value) {
-            (None, Some(current)) => (
-                Some(current.clone_box()),
-                ComponentTrackerEvent::Added(current.clone_box()),
-            ),
-            (Some(previous), None) => (None, ComponentTrackerEvent::Removed(previous.clone_box())),
-            (Some(previous), Some(current)) if previous != current => (
-                Some(current.clone_box()),
-                ComponentTrackerEvent::Changed {
-                    old: previous.clone_box(),
-                    new: current.clone_box(),
-                },
-            ),
-            _ => return None,
+            .and_then(|c| c.as_serializable())
+            .filter(|_| with_values);
+
+        let change = match ticks {
+            Some(_) if !was_present => Some(ComponentChange::Added),
+            Some(ticks) if ticks.is_changed(last_checked) => Some(ComponentChange::Changed),
+            _ => None,
         };
 
-        self.previous_value = new_previous_value;
-        initialized.then_some(event)
+        if let Some(change) = change {
+            let event = match (change, self.previous_value.take(), current_value) {
+                (ComponentChange::Added, _, Some(current)) => {
+                    Some(ComponentTrackerEvent::Added(current.clone_box()))
+                }
+                (ComponentChange::Changed, Some(old), Some(current)) => {
+                    Some(ComponentTrackerEvent::Changed {
+                        old,
+                        new: current.clone_box(),
+                    })
+                }
+                _ => None,
+            };
+            changes.push((change, event));
+        }
+
+        if change.is_some() || self.previous_value.is_none() {
+            self.previous_value = current_value.map(|c| c.clone_box());
+        }
+        if !with_values || ticks.is_none() {
+            self.previous_value = None;
+        }
+
+        if !initialized {
+            changes.clear();
+        }
+        changes
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrackerSenders {
+    changes: EventSender<ComponentChange>,
+    events: EventSender<ComponentTrackerEvent<dyn SerializableComponent>>,
+}
+
+impl TrackerSenders {
+    fn receiver_count(&self) -> u32 {
+        self.changes.receiver_count() + self.events.receiver_count()
+    }
+
+    fn update(&self, tracker: &mut EntityComponentTracker, ecs_world: &World) {
+        let with_values = self.events.receiver_count() > 0;
+        for (change, event) in tracker.update(ecs_world, with_values) {
+            self.changes.send(change);
+            if let Some(event) = event {
+                self.events.send(event);
+            }
+        }
     }
 }
 
-type TrackerSenderPair = (
-    EntityComponentTracker,
-    EventSender<ComponentTrackerEvent<dyn SerializableComponent>>,
-);
+type TrackerSenderPair = (EntityComponentTracker, TrackerSenders);
 
+/// Sends the changes to the components that something is listening to. Updated by [`World::update_change_tracker`]
+/// and friends.
 #[derive(Debug, Default)]
 pub struct GlobalComponentTracker {
     entity_tracker_maps: Mutex<BTreeMap<EntityId, BTreeMap<ComponentId, TrackerSenderPair>>>,
@@ -80,30 +198,24 @@ impl GlobalComponentTracker {
             .try_lock()
             .unwrap()
             .retain(|&entity_id, trackers| {
-                if !ecs_world.has_entity(entity_id) {
-                    return false;
-                }
-
-                trackers.retain(|_, (_, event_sender)| event_sender.receiver_count() > 0);
+                let exists = ecs_world.has_entity(entity_id);
+                trackers.retain(|_, (tracker, senders)| {
+                    senders.receiver_count() > 0 && (exists || !tracker.has_been_removed)
+                });
 
-                if trackers.is_empty() {
-                    return false;
-                }
-
-                true
+                !trackers.is_empty()
             });
     }
 
     pub fn update(&self, ecs_world: &World) {
-        self.clean(ecs_world);
-
         for (_, trackers) in self.entity_tracker_maps.try_lock().unwrap().iter_mut() {
-            for (_, (tracker, event_sender)) in trackers.iter_mut() {
-                if let Some(event) = tracker.update(ecs_world) {
-                    event_sender.send(event);
-                }
+            for (_, (tracker, senders)) in trackers.iter_mut() {
+                senders.update(tracker, ecs_world);
             }
         }
+
+        // after sending, so that the removals of deleted entities are seen
+        self.clean(ecs_world);
     }
 
     pub fn update_entity(&self, ecs_world: &World, entity_id: EntityId) {
@@ -113,10 +225,8 @@ impl GlobalComponentTracker {
             .unwrap()
             .get_mut(&entity_id)
         {
-            for (_, (tracker, event_sender)) in trackers.iter_mut() {
-                if let Some(event) = tracker.update(ecs_world) {
-                    event_sender.send(event);
-                }
+            for (_, (tracker, senders)) in trackers.iter_mut() {
+                senders.update(tracker, ecs_world);
             }
         }
     }
@@ -132,13 +242,47 @@ impl GlobalComponentTracker {
             .try_lock()
             .unwrap()
             .get_mut(&entity_id)
-            && let Some((tracker, event_sender)) = trackers.get_mut(&component_id)
-            && let Some(event) = tracker.update(ecs_world)
+            && let Some((tracker, senders)) = trackers.get_mut(&component_id)
         {
-            event_sender.send(event);
+            senders.update(tracker, ecs_world);
         }
     }
 
+    /// Called by the world whenever a component is removed from an entity.
+    pub(crate) fn on_component_deleted(&mut self, entity_id: EntityId, component: &dyn Component) {
+        if let Some((tracker, senders)) = self
+            .entity_tracker_maps
+            .get_mut()
+            .unwrap()
+            .get_mut(&entity_id)
+            .and_then(|trackers| trackers.get_mut(&component.component_id()))
+        {
+            tracker.on_removed(component, senders.events.receiver_count() > 0);
+        }
+    }
+
+    pub fn subscribe_changes(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) -> EventReceiver<ComponentChange> {
+        self.entity_tracker_maps
+            .try_lock()
+            .unwrap()
+            .entry(entity_id)
+            .or_default()
+            .entry(component_id)
+            .or_insert_with(|| {
+                (
+                    EntityComponentTracker::new(entity_id, component_id),
+                    TrackerSenders::default(),
+                )
+            })
+            .1
+            .changes
+            .subscribe()
+    }
+
     /// # Safety
     ///
     /// The caller must ensure that `T` is the concrete type associated with `component_id`.
@@ -157,7 +301,7 @@ impl GlobalComponentTracker {
             .or_insert_with(|| {
                 (
                     EntityComponentTracker::new(entity_id, component_id),
-                    EventSender::default(),
+                    TrackerSenders::default(),
                 )
             });
 
@@ -165,7 +309,7 @@ impl GlobalComponentTracker {
             std::mem::transmute::<
                 &EventSender<ComponentTrackerEvent<dyn SerializableComponent>>,
                 &EventSender<ComponentTrackerEvent<T>>,
-            >(&pair.1)
+            >(&pair.1.events)
         }
     }
 
@@ -179,3 +323,89 @@ impl GlobalComponentTracker {
         unsafe { self.get_event_sender_typed::<dyn SerializableComponent>(entity_id, component_id) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct TrackerHealth(i32);
+
+    /// Not serializable, so only visible through [`World::component_changes`].
+    #[derive(Debug, Component)]
+    struct TrackerCache(i32);
+
+    fn changes(receiver: &EventReceiver<ComponentChange>) -> Vec<ComponentChange> {
+        receiver
+            .recv_all()
+            .into_iter()
+            .map(|change| *change)
+            .collect()
+    }
+
+    #[test]
+    fn changes_of_non_serializable_components_are_sent() {
+        let mut world = World::default();
+        let entity_id = world.new_entity_id();
+        let receiver = world.component_changes(entity_id, TrackerCache::COMPONENT_ID);
+        world.update_change_tracker();
+
+        world.set_component(entity_id, TrackerCache(1));
+        world.update_change_tracker();
+        assert_eq!(changes(&receiver), [ComponentChange::Added]);
+
+        world.update_change_tracker();
+        assert_eq!(changes(&receiver), []);
+
+        TrackerCache::query_one_mut(&mut world, entity_id)
+            .unwrap()
+            .0 = 2;
+        world.update_change_tracker();
+        assert_eq!(changes(&receiver), [ComponentChange::Changed]);
+
+        world.delete_component(entity_id, TrackerCache::COMPONENT_ID);
+        world.set_component(entity_id, TrackerCache(3));
+        world.update_change_tracker();
+        assert_eq!(
+            changes(&receiver),
+            [ComponentChange::Removed, ComponentChange::Added]
+        );
+    }
+
+    #[test]
+    fn events_carry_old_and_new_values() {
+        let mut world = World::default();
+        let entity_id = world.spawn((TrackerHealth(10),));
+        let receiver = world
+            .get_component_changed_event_sender(entity_id, TrackerHealth::COMPONENT_ID)
+            .subscribe();
+        world.update_change_tracker();
+
+        TrackerHealth::query_one_mut(&mut world, entity_id)
+            .unwrap()
+            .0 = 5;
+        world.update_change_tracker();
+        world.delete_entity(entity_id);
+        world.update_change_tracker();
+
+        let events: Vec<_> = receiver
+            .recv_all()
+            .into_iter()
+            .map(|event| (*event).clone())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ComponentTrackerEvent::Changed {
+                    old: Box::new(TrackerHealth(10)) as Box<dyn SerializableComponent>,
+                    new: Box::new(TrackerHealth(5)),
+                },
+                ComponentTrackerEvent::Removed(Box::new(TrackerHealth(5))),
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::time::Instant;
+
+use hydrogen_net::server_client::ClientId;
+
+use crate::{
+    ecs_net::{NetEcsCommand, Replicate, ServerEntityId},
+    interpolation::Interpolation,
+    migration::MigrationError,
+    prediction::Prediction,
+    world::World,
+};
+
+/// Applies the commands a client receives from the server, predicting the entities the client owns and interpolating
+/// the rest.
+///
+/// Every command goes through the [`Prediction`] first, then the [`Interpolation`] unless it's a new value of a
+/// component of an owned entity, and is then applied to the world if neither took it.
+#[derive(Debug)]
+pub struct ClientNet {
+    pub client_id: ClientId,
+    pub prediction: Option<Prediction>,
+    pub interpolation: Option<Interpolation>,
+}
+
+impl ClientNet {
+    pub fn new(client_id: ClientId) -> Self {
+        Self {
+            client_id,
+            prediction: None,
+            interpolation: None,
+        }
+    }
+
+    pub fn with_prediction(mut self, prediction: Prediction) -> Self {
+        self.prediction = Some(prediction);
+        self
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = Some(interpolation);
+        self
+    }
+
+    /// Whether the client owns an entity, going by the [`Replicate`] component the server sent.
+    pub fn owns(&self, world: &World, server_entity_id: ServerEntityId) -> bool {
+        world
+            .try_entity_id_from_server(server_entity_id)
+            .and_then(|entity_id| Replicate::query_one(world, entity_id))
+            .is_some_and(|replicate| replicate.owner == Some(self.client_id))
+    }
+
+    /// Use instead of [`World::execute_net_command`] for commands from the server.
+    pub fn execute_net_command(
+        &mut self,
+        world: &mut World,
+        command: NetEcsCommand,
+        now: Instant,
+    ) -> Result<(), MigrationError> {
+        if let NetEcsCommand::Batch(commands) = command {
+            for command in world.migrate_net_commands(commands)? {
+                self.execute_net_command(world, command, now)?;
+            }
+            return Ok(());
+        }
+
+        let mut command = Some(command);
+        if let Some(prediction) = &mut self.prediction
+            && let Some(unhandled) = command.take()
+        {
+            command = prediction.handle_net_command(world, unhandled)?;
+        }
+
+        // owned entities are shown as they are, without the interpolation's delay
+        let is_owned_value = command.as_ref().is_some_and(|command| {
+            matches!(
+                command,
+                NetEcsCommand::SetComponent(..) | NetEcsCommand::SetComponentDelta(..)
+            ) && command
+                .server_entity_id()
+                .is_some_and(|server_entity_id| self.owns(world, server_entity_id))
+        });
+        if let Some(interpolation) = &mut self.interpolation
+            && !is_owned_value
+            && let Some(unhandled) = command.take()
+        {
+            command = interpolation.handle_net_command(world, unhandled, now)?;
+        }
+
+        if let Some(command) = command {
+            world.execute_net_command(command)?;
+        }
+        Ok(())
+    }
+
+    /// Sets every interpolated component of the entities the client doesn't own to its value at `now`. See
+    /// [`Interpolation::update`].
+    pub fn update(&mut self, world: &mut World, now: Instant) {
+        let Some(interpolation) = &self.interpolation else {
+            return;
+        };
+
+        // entities the client has been given since they were spawned stop being interpolated
+        let owned = interpolation
+            .interpolated_entities()
+            .into_iter()
+            .filter(|&server_entity_id| self.owns(world, server_entity_id))
+            .collect::<Vec<_>>();
+
+        let Some(interpolation) = &mut self.interpolation else {
+            return;
+        };
+        for server_entity_id in owned {
+            interpolation.forget(server_entity_id);
+        }
+        interpolation.update(world, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hydrogen_data_structures::selection::Selection;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{
+        component::{SerializableComponent, SerializableComponentBundle},
+        ecs_net::tests::connect,
+        entity::EntityId,
+        interpolation::Interpolate,
+    };
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct NetPosition(f32);
+
+    impl Interpolate for NetPosition {
+        fn interpolate(&self, other: &Self, t: f32) -> Self {
+            Self(self.0.interpolate(&other.0, t))
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct NetMove(f32);
+
+    const CLIENT_ID: ClientId = ClientId(1);
+    const DELAY: Duration = Duration::from_millis(100);
+
+    fn move_by(world: &mut World, entity_id: EntityId, input: &dyn SerializableComponent) {
+        let distance = input.downcast_ref::<NetMove>().unwrap().0;
+        if let Some(position) = NetPosition::query_one_mut(world, entity_id) {
+            position.0 += distance;
+        }
+    }
+
+    fn spawn(server_entity_id: ServerEntityId, owner: Option<ClientId>) -> NetEcsCommand {
+        let mut bundle = SerializableComponentBundle::new();
+        bundle.set_component(NetPosition(0.0));
+        bundle.set_component(Replicate {
+            server_entity_id,
+            owner,
+            replicate_to: Selection::all(),
+            client_writable: Selection::none(),
+            replicated_components: Selection::all(),
+            auto_replicate_changes: Selection::all(),
+        });
+        NetEcsCommand::SpawnEntity(server_entity_id, bundle)
+    }
+
+    fn position(world: &World, entity_id: EntityId) -> f32 {
+        NetPosition::query_one(world, entity_id).unwrap().0
+    }
+
+    #[test]
+    fn owned_entities_are_predicted_and_the_rest_interpolated() {
+        let (_server_comm, mut comm) = connect();
+        let mut world = World::default();
+        let mut predicted_components = Selection::none();
+        predicted_components.allow(NetPosition::COMPONENT_ID);
+        let mut interpolation = Interpolation::new(DELAY, Duration::ZERO);
+        interpolation.interpolate::<NetPosition>();
+        let mut client_net = ClientNet::new(CLIENT_ID)
+            .with_prediction(Prediction::new(predicted_components, move_by))
+            .with_interpolation(interpolation);
+
+        let start = Instant::now();
+        let owned = ServerEntityId(EntityId(0));
+        let remote = ServerEntityId(EntityId(1));
+        client_net
+            .execute_net_command(
+                &mut world,
+                NetEcsCommand::Batch(vec![spawn(owned, Some(CLIENT_ID)), spawn(remote, None)]),
+                start,
+            )
+            .unwrap();
+        let owned_entity_id = world.entity_id_from_server(owned);
+        let remote_entity_id = world.entity_id_from_server(remote);
+        assert!(client_net.owns(&world, owned));
+        assert!(!client_net.owns(&world, remote));
+
+        // the owned entity moves as soon as there's an input or a new value
+        let prediction = client_net.prediction.as_mut().unwrap();
+        prediction.input(&mut world, &mut comm, owned, NetMove(1.0));
+        assert_eq!(position(&world, owned_entity_id), 1.0);
+
+        let later = start + DELAY;
+        client_net
+            .execute_net_command(
+                &mut world,
+                NetEcsCommand::Batch(vec![
+                    NetEcsCommand::SetComponent(owned, Box::new(NetPosition(5.0))),
+                    NetEcsCommand::SetComponent(remote, Box::new(NetPosition(10.0))),
+                ]),
+                later,
+            )
+            .unwrap();
+        assert_eq!(position(&world, owned_entity_id), 6.0);
+
+        // while the remote one catches up with a delay
+        assert_eq!(position(&world, remote_entity_id), 0.0);
+        client_net.update(&mut world, later + DELAY / 2);
+        assert_eq!(position(&world, remote_entity_id), 5.0);
+        client_net.update(&mut world, later + DELAY);
+        assert_eq!(position(&world, remote_entity_id), 10.0);
+        assert_eq!(position(&world, owned_entity_id), 6.0);
+    }
+}
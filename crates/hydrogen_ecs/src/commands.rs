@@ -0,0 +1,185 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering},
+};
+
+use crate::{
+    component::{Bundle, Component, ComponentId},
+    entity::EntityId,
+    world::World,
+};
+
+#[derive(Debug)]
+enum Command {
+    Insert(EntityId, Box<dyn Component>),
+    Remove(EntityId, ComponentId),
+    Despawn(EntityId),
+}
+
+/// A buffer of structural changes to a [`World`] (spawning, inserting, removing, despawning) that are recorded while the
+/// world is borrowed, then applied all at once with [`Commands::apply`].
+///
+/// Created with [`World::commands`].
+#[derive(Debug)]
+pub struct Commands {
+    next_entity_id: Arc<AtomicU32>,
+    queue: Vec<Command>,
+}
+
+impl Commands {
+    pub(crate) fn new(next_entity_id: Arc<AtomicU32>) -> Self {
+        Self {
+            next_entity_id,
+            queue: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Reserves a new [`EntityId`] right away. The entity will not exist in the world until a component is inserted
+    /// into it and the commands are applied.
+    pub fn spawn(&mut self) -> EntityId {
+        self.next_entity_id.fetch_add(1, Ordering::Relaxed).into()
+    }
+
+    /// Reserves a new [`EntityId`] right away and records the insertion of every component in `bundle` into it.
+    pub fn spawn_bundle(&mut self, bundle: impl Bundle) -> EntityId {
+        let entity_id = self.spawn();
+        self.insert_bundle(entity_id, bundle);
+        entity_id
+    }
+
+    pub fn insert<T: Component>(&mut self, entity_id: EntityId, component: T) {
+        self.insert_boxed(entity_id, Box::new(component));
+    }
+
+    pub fn insert_boxed(&mut self, entity_id: EntityId, component: Box<dyn Component>) {
+        self.queue.push(Command::Insert(entity_id, component));
+    }
+
+    pub fn insert_bundle(&mut self, entity_id: EntityId, bundle: impl Bundle) {
+        for component in bundle.into_components() {
+            self.insert_boxed(entity_id, component);
+        }
+    }
+
+    pub fn remove(&mut self, entity_id: EntityId, component_id: ComponentId) {
+        self.queue.push(Command::Remove(entity_id, component_id));
+    }
+
+    pub fn despawn(&mut self, entity_id: EntityId) {
+        self.queue.push(Command::Despawn(entity_id));
+    }
+
+    /// Applies every recorded command to `world` in the order they were recorded, leaving the buffer empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `world` is not the world these commands were created from.
+    pub fn apply(&mut self, world: &mut World) {
+        assert!(
+            world.shares_entity_id_counter(&self.next_entity_id),
+            "Commands applied to a different World than the one they were created from"
+        );
+
+        for command in self.queue.drain(..) {
+            match command {
+                Command::Insert(entity_id, component) => {
+                    world.set_component_boxed(entity_id, component);
+                }
+                Command::Remove(entity_id, component_id) => {
+                    world.delete_component(entity_id, component_id);
+                }
+                Command::Despawn(entity_id) => {
+                    world.delete_entity(entity_id);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Component)]
+    struct CommandsHealth(i32);
+
+    #[derive(Debug, Clone, PartialEq, Component)]
+    struct CommandsArmor(i32);
+
+    #[test]
+    fn commands_are_applied_in_order() {
+        let mut world = World::default();
+        let existing = world.spawn((CommandsHealth(1),));
+
+        let mut commands = world.commands();
+        let spawned = commands.spawn_bundle((CommandsHealth(10),));
+        commands.insert(spawned, CommandsArmor(5));
+        commands.insert(spawned, CommandsHealth(20));
+        commands.remove(spawned, CommandsArmor::COMPONENT_ID);
+        commands.insert(existing, CommandsArmor(3));
+        commands.despawn(existing);
+        assert_eq!(commands.len(), 6);
+
+        // nothing happens until the commands are applied
+        assert!(!world.has_entity(spawned));
+        assert!(world.has_entity(existing));
+
+        world.apply_commands(&mut commands);
+        assert!(commands.is_empty());
+
+        assert_eq!(
+            CommandsHealth::query_one(&world, spawned),
+            Some(&CommandsHealth(20))
+        );
+        assert_eq!(CommandsArmor::query_one(&world, spawned), None);
+        assert!(!world.has_entity(existing));
+    }
+
+    #[test]
+    fn reserved_entity_ids_do_not_collide() {
+        let mut world = World::default();
+        let mut commands = world.commands();
+        let mut other_commands = world.commands();
+
+        let mut entity_ids = BTreeSet::new();
+        for _ in 0..10 {
+            assert!(entity_ids.insert(commands.spawn()));
+            assert!(entity_ids.insert(world.new_entity_id()));
+            assert!(entity_ids.insert(other_commands.spawn()));
+            assert!(entity_ids.insert(world.spawn((CommandsHealth(0),))));
+        }
+
+        // spawning through the commands afterwards doesn't reuse any of them
+        let spawned = commands.spawn_bundle((CommandsHealth(1),));
+        assert!(entity_ids.insert(spawned));
+        world.apply_commands(&mut commands);
+        assert_eq!(
+            CommandsHealth::query_one(&world, spawned),
+            Some(&CommandsHealth(1))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn applying_to_another_world_panics() {
+        let world = World::default();
+        let mut other_world = World::default();
+        let mut commands = world.commands();
+        commands.despawn(EntityId(0));
+        commands.apply(&mut other_world);
+    }
+}
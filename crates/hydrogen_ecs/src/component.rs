@@ -1,16 +1,19 @@
-use crate::entity::EntityId;
+use crate::{
+    change_detection::{ComponentTicks, Tick},
+    entity::EntityId,
+};
 use derive_more::*;
 use dyn_clone::DynClone;
 use hydrogen_core::dyn_util::DynPartialEq;
 use serde::{Deserialize, Serialize};
 use std::{
-    any::Any,
+    any::{Any, TypeId},
     array,
     collections::{BTreeMap, VecDeque},
     fmt, mem, ptr,
 };
 
-pub use hydrogen_ecs_proc_macro::{Component, SerializableComponent};
+pub use hydrogen_ecs_proc_macro::{Bundle, Component, SerializableComponent};
 
 #[derive(
     Debug,
@@ -33,6 +36,8 @@ pub struct ComponentId(pub u64);
 
 pub trait Component: fmt::Debug + Any + 'static + Send + Sync {
     fn component_id(&self) -> ComponentId;
+    /// Set with `#[component(version = N)]`, defaults to 0. See [`ComponentMigrations`](crate::migration::ComponentMigrations).
+    fn component_version(&self) -> u32;
     fn display_name(&self) -> &'static str;
     fn any_ref(&self) -> &dyn Any;
 
@@ -41,6 +46,14 @@ pub trait Component: fmt::Debug + Any + 'static + Send + Sync {
     fn as_serializable_mut(&mut self) -> Option<&mut dyn SerializableComponent>;
 }
 
+/// Implemented by the [`Component`] and [`SerializableComponent`] derives, exposing a component type's
+/// [`ComponentId`] without needing an instance of it.
+pub trait ComponentType: Component + Sized {
+    const COMPONENT_ID: ComponentId;
+    const COMPONENT_VERSION: u32;
+    const DISPLAY_NAME: &'static str;
+}
+
 impl dyn Component {
     pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
         self.any_ref().downcast_ref()
@@ -53,17 +66,74 @@ pub trait SerializableComponent: Component + DynClone + DynPartialEq + Send + Sy
 }
 dyn_clone::clone_trait_object!(SerializableComponent);
 
+impl dyn SerializableComponent {
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.any_ref().downcast_ref()
+    }
+}
+
 impl PartialEq for dyn SerializableComponent {
     fn eq(&self, other: &Self) -> bool {
         self.dyn_eq(other.as_any())
     }
 }
 
+/// Anything that can be broken down into a group of components to be inserted into an entity at once, such as a
+/// [`ComponentBundle`], a [`SerializableComponentBundle`], a tuple of components, or a struct deriving [`Bundle`].
+pub trait Bundle {
+    fn into_components(self) -> Vec<Box<dyn Component>>;
+}
+
+impl Bundle for ComponentBundle {
+    fn into_components(self) -> Vec<Box<dyn Component>> {
+        self.components.into_values().collect()
+    }
+}
+
+impl Bundle for SerializableComponentBundle {
+    fn into_components(self) -> Vec<Box<dyn Component>> {
+        self.components
+            .into_values()
+            .map(|component| component as Box<dyn Component>)
+            .collect()
+    }
+}
+
+macro_rules! impl_bundle_for_tuple {
+    ($($component:ident),*) => {
+        impl<$($component: Component),*> Bundle for ($($component,)*) {
+            #[allow(non_snake_case)]
+            fn into_components(self) -> Vec<Box<dyn Component>> {
+                let ($($component,)*) = self;
+                vec![$(Box::new($component)),*]
+            }
+        }
+    };
+}
+
+impl_bundle_for_tuple!(A);
+impl_bundle_for_tuple!(A, B);
+impl_bundle_for_tuple!(A, B, C);
+impl_bundle_for_tuple!(A, B, C, D);
+impl_bundle_for_tuple!(A, B, C, D, E);
+impl_bundle_for_tuple!(A, B, C, D, E, F);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G, H);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G, H, I);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_bundle_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
 /// The container for every instance of a given type of component in a world.
 #[derive(Debug)]
 pub struct ComponentSet {
     component_id: ComponentId,
+    /// The concrete type of the components, fixed by the first one set. Queries cast to it without checking.
+    type_id: Option<TypeId>,
     components: Vec<Option<Box<dyn Component>>>,
+    ticks: Vec<ComponentTicks>,
+    /// The newest tick any component in the set was added or changed at.
+    last_changed: Tick,
     entity_component_indices: Vec<Option<usize>>,
     deleted_component_indices: VecDeque<usize>,
 }
@@ -72,7 +142,10 @@ impl ComponentSet {
     pub fn new(component_id: ComponentId) -> Self {
         Self {
             component_id,
+            type_id: None,
             components: vec![],
+            ticks: vec![],
+            last_changed: Tick::default(),
             entity_component_indices: vec![],
             deleted_component_indices: VecDeque::new(),
         }
@@ -124,17 +197,46 @@ impl ComponentSet {
         self.components.get(component_index)?.as_ref()
     }
 
-    pub fn get_mut(&mut self, entity_id: EntityId) -> Option<&mut Box<dyn Component>> {
+    pub fn get_ticks(&self, entity_id: EntityId) -> Option<ComponentTicks> {
         let index = entity_id.0 as usize;
 
         let component_index = self.entity_component_indices.get(index)?.to_owned()?;
+        self.ticks.get(component_index).copied()
+    }
+
+    /// Marks the entity's component as changed at `tick`. Returns `false` if the entity doesn't have the component.
+    pub fn set_changed(&mut self, entity_id: EntityId, tick: Tick) -> bool {
+        let index = entity_id.0 as usize;
+
+        if let Some(&Some(component_index)) = self.entity_component_indices.get(index) {
+            self.ticks[component_index].changed = tick;
+            self.last_changed = self.last_changed.max(tick);
+            return true;
+        }
+
+        false
+    }
+
+    /// Gets the entity's component mutably, marking it as changed at `tick`.
+    pub fn get_mut(&mut self, entity_id: EntityId, tick: Tick) -> Option<&mut Box<dyn Component>> {
+        let index = entity_id.0 as usize;
+
+        let component_index = self.entity_component_indices.get(index)?.to_owned()?;
+        self.ticks[component_index].changed = tick;
+        self.last_changed = self.last_changed.max(tick);
         self.components.get_mut(component_index)?.as_mut()
     }
 
+    /// The newest tick that any component in the set was added or changed at, for skipping sets that haven't changed.
+    pub fn last_changed(&self) -> Tick {
+        self.last_changed
+    }
+
     pub fn set(
         &mut self,
         entity_id: EntityId,
         entry: Box<dyn Component>,
+        tick: Tick,
     ) -> Option<Box<dyn Component>> {
         let index = entity_id.0 as usize;
 
@@ -145,18 +247,29 @@ impl ComponentSet {
             entry.component_id().0,
             entry.display_name()
         );
+        let type_id = entry.any_ref().type_id();
+        assert!(
+            *self.type_id.get_or_insert(type_id) == type_id,
+            "type mismatch for component {}: {} (version {}) isn't the type already stored",
+            self.component_id.0,
+            entry.display_name(),
+            entry.component_version()
+        );
 
-        if let Some(old_entry) = self.get_mut(entity_id) {
+        if let Some(old_entry) = self.get_mut(entity_id, tick) {
             return Some(mem::replace(old_entry, entry));
         }
 
         self.reserve_entity_component_indices(index);
+        self.last_changed = self.last_changed.max(tick);
 
         if let Some(component_index) = self.deleted_component_indices.pop_front() {
             self.components[component_index] = Some(entry);
+            self.ticks[component_index] = ComponentTicks::new(tick);
             self.entity_component_indices[index] = Some(component_index);
         } else {
             self.components.push(Some(entry));
+            self.ticks.push(ComponentTicks::new(tick));
             self.entity_component_indices[index] = Some(self.components.len() - 1);
         };
 
@@ -186,6 +299,14 @@ impl ComponentBundle {
         Self::default()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
     pub fn has_component(&self, component_id: ComponentId) -> bool {
         self.components.contains_key(&component_id)
     }
@@ -230,16 +351,20 @@ impl ComponentBundle {
     }
 
     pub fn set_component<T: Component>(&mut self, component: T) -> Option<T> {
-        if let Some(old_component) = self
-            .components
-            .insert(component.component_id(), Box::new(component))
-        {
+        if let Some(old_component) = self.set_component_boxed(Box::new(component)) {
             return Some(*Box::<dyn Any + 'static>::downcast::<T>(old_component).ok()?);
         }
 
         None
     }
 
+    pub fn set_component_boxed(
+        &mut self,
+        component: Box<dyn Component>,
+    ) -> Option<Box<dyn Component>> {
+        self.components.insert(component.component_id(), component)
+    }
+
     pub fn delete_component(&mut self, component_id: ComponentId) -> Option<Box<dyn Component>> {
         self.components.remove(&component_id)
     }
@@ -249,22 +374,7 @@ impl ComponentBundle {
         with: [ComponentId; WITH],
         without: [ComponentId; WITHOUT],
     ) -> Option<[&Box<dyn Component>; WITH]> {
-        if with.is_empty() {
-            return None;
-        }
-
-        for &excluded_component_id in without.iter() {
-            if self.has_component(excluded_component_id) {
-                return None;
-            }
-        }
-
-        let mut component_slots: [Option<&Box<dyn Component>>; WITH] = array::from_fn(|_| None);
-        for (index, slot) in component_slots.iter_mut().enumerate() {
-            *slot = Some(self.get_component(with[index])?)
-        }
-
-        Some(array::from_fn(|index| component_slots[index].unwrap()))
+        self.query_at((), with, without)
     }
 
     pub fn query_mut<const WITH: usize, const WITHOUT: usize>(
@@ -272,31 +382,7 @@ impl ComponentBundle {
         with: [ComponentId; WITH],
         without: [ComponentId; WITHOUT],
     ) -> Option<[&mut Box<dyn Component>; WITH]> {
-        if with.is_empty() {
-            return None;
-        }
-
-        for &excluded_component_id in without.iter() {
-            if self.has_component(excluded_component_id) {
-                return None;
-            }
-        }
-
-        let mut component_slots: [Option<&mut Box<dyn Component>>; WITH] = array::from_fn(|_| None);
-        for (index, slot) in component_slots.iter_mut().enumerate() {
-            // ew
-            unsafe {
-                *slot = Some(
-                    ((self.get_component(with[index])?) as *const Box<dyn Component>
-                        as *mut Box<dyn Component>)
-                        .as_mut()?,
-                )
-            }
-        }
-
-        Some(array::from_fn(|index| {
-            component_slots[index].take().unwrap()
-        }))
+        self.query_at_mut((), with, without)
     }
 }
 
@@ -313,6 +399,14 @@ impl SerializableComponentBundle {
         Self::default()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
     pub fn has_component(&self, component_id: ComponentId) -> bool {
         self.components.contains_key(&component_id)
     }
@@ -345,17 +439,27 @@ impl SerializableComponentBundle {
             .map(|(&id, component)| (id, component))
     }
 
+    pub fn into_serializable_components(
+        self,
+    ) -> impl Iterator<Item = Box<dyn SerializableComponent>> {
+        self.components.into_values()
+    }
+
     pub fn set_component<T: SerializableComponent>(&mut self, component: T) -> Option<T> {
-        if let Some(old_component) = self
-            .components
-            .insert(component.component_id(), Box::new(component))
-        {
+        if let Some(old_component) = self.set_component_boxed(Box::new(component)) {
             return Some(*Box::<dyn Any + 'static>::downcast::<T>(old_component).ok()?);
         }
 
         None
     }
 
+    pub fn set_component_boxed(
+        &mut self,
+        component: Box<dyn SerializableComponent>,
+    ) -> Option<Box<dyn SerializableComponent>> {
+        self.components.insert(component.component_id(), component)
+    }
+
     pub fn delete_component(
         &mut self,
         component_id: ComponentId,
@@ -368,70 +472,316 @@ impl SerializableComponentBundle {
         with: [ComponentId; WITH],
         without: [ComponentId; WITHOUT],
     ) -> Option<[&Box<dyn SerializableComponent>; WITH]> {
-        if with.is_empty() {
+        self.query_at((), with, without)
+    }
+
+    pub fn query_mut<const WITH: usize, const WITHOUT: usize>(
+        &mut self,
+        with: [ComponentId; WITH],
+        without: [ComponentId; WITHOUT],
+    ) -> Option<[&mut Box<dyn SerializableComponent>; WITH]> {
+        self.query_at_mut((), with, without)
+    }
+}
+
+/// How a [`ComponentStorage`] holds its components, so that its queries return them the same way as its other getters.
+pub trait StoredComponent {
+    fn as_component(&self) -> &dyn Component;
+    fn as_component_mut(&mut self) -> &mut dyn Component;
+}
+
+impl StoredComponent for Box<dyn Component> {
+    fn as_component(&self) -> &dyn Component {
+        self.as_ref()
+    }
+
+    fn as_component_mut(&mut self) -> &mut dyn Component {
+        self.as_mut()
+    }
+}
+
+impl StoredComponent for Box<dyn SerializableComponent> {
+    fn as_component(&self) -> &dyn Component {
+        self.as_ref()
+    }
+
+    fn as_component_mut(&mut self) -> &mut dyn Component {
+        self.as_mut()
+    }
+}
+
+/// Panics if a mutable query asks for the same component twice.
+pub(crate) fn assert_distinct(component_ids: &[ComponentId]) {
+    for (index, component_id) in component_ids.iter().enumerate() {
+        assert!(
+            !component_ids[..index].contains(component_id),
+            "component {} is queried mutably more than once",
+            component_id.0
+        );
+    }
+}
+
+/// Anything that stores components under keys: a [`World`](crate::world::World) stores them per entity, a bundle stores
+/// a single set of them under `()`, and a slice or [`Vec`] of bundles stores one set per index. Queries written against
+/// this trait, such as [`query_storage!`], work the same over all of them.
+pub trait ComponentStorage {
+    type Key: Copy;
+    type Stored: StoredComponent + ?Sized;
+
+    /// Every key that has at least one component.
+    fn component_keys(&self) -> Vec<Self::Key>;
+    fn contains_key(&self, key: Self::Key) -> bool;
+    fn component_at(&self, key: Self::Key, component_id: ComponentId) -> Option<&Self::Stored>;
+    fn component_at_mut(
+        &mut self,
+        key: Self::Key,
+        component_id: ComponentId,
+    ) -> Option<&mut Self::Stored>;
+
+    fn has_component_at(&self, key: Self::Key, component_id: ComponentId) -> bool {
+        self.component_at(key, component_id).is_some()
+    }
+
+    /// The components in `with` stored under `key`, if it has all of them and none of those in `without`. An empty
+    /// `with` matches any key that has at least one component.
+    fn query_at<const WITH: usize, const WITHOUT: usize>(
+        &self,
+        key: Self::Key,
+        with: [ComponentId; WITH],
+        without: [ComponentId; WITHOUT],
+    ) -> Option<[&Self::Stored; WITH]> {
+        if with.is_empty() && !self.contains_key(key) {
             return None;
         }
 
         for &excluded_component_id in without.iter() {
-            if self.has_component(excluded_component_id) {
+            if self.has_component_at(key, excluded_component_id) {
                 return None;
             }
         }
 
-        let mut component_slots: [Option<&Box<dyn SerializableComponent>>; WITH] =
-            array::from_fn(|_| None);
+        let mut component_slots: [Option<&Self::Stored>; WITH] = [None; WITH];
         for (index, slot) in component_slots.iter_mut().enumerate() {
-            *slot = Some(self.get_component(with[index])?)
+            *slot = Some(self.component_at(key, with[index])?)
         }
 
         Some(array::from_fn(|index| component_slots[index].unwrap()))
     }
 
-    pub fn query_mut<const WITH: usize, const WITHOUT: usize>(
+    /// Like [`ComponentStorage::query_at`], but mutable.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `with` contains the same component twice, since it would be borrowed mutably twice.
+    fn query_at_mut<const WITH: usize, const WITHOUT: usize>(
         &mut self,
+        key: Self::Key,
         with: [ComponentId; WITH],
         without: [ComponentId; WITHOUT],
-    ) -> Option<[&mut Box<dyn SerializableComponent>; WITH]> {
-        if with.is_empty() {
-            return None;
+    ) -> Option<[&mut Self::Stored; WITH]> {
+        assert_distinct(&with);
+        let _ = self.query_at(key, with, without)?;
+
+        let mut component_slots: [Option<*mut Self::Stored>; WITH] = [None; WITH];
+        for (index, slot) in component_slots.iter_mut().enumerate() {
+            *slot = Some(self.component_at_mut(key, with[index])?);
         }
 
-        for &excluded_component_id in without.iter() {
-            if self.has_component(excluded_component_id) {
-                return None;
+        // every component ID in `with` is different, so none of these alias
+        Some(component_slots.map(|component| unsafe { &mut *component.unwrap() }))
+    }
+
+    fn query_all<const WITH: usize, const WITHOUT: usize>(
+        &self,
+        with: [ComponentId; WITH],
+        without: [ComponentId; WITHOUT],
+    ) -> impl Iterator<Item = (Self::Key, [&Self::Stored; WITH])> {
+        self.component_keys()
+            .into_iter()
+            .filter_map(move |key| Some((key, self.query_at(key, with, without)?)))
+    }
+
+    /// Like [`ComponentStorage::query_all`], but mutable.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `with` contains the same component twice.
+    fn query_all_mut<const WITH: usize, const WITHOUT: usize>(
+        &mut self,
+        with: [ComponentId; WITH],
+        without: [ComponentId; WITHOUT],
+    ) -> impl Iterator<Item = (Self::Key, [&mut Self::Stored; WITH])> {
+        assert_distinct(&with);
+        let keys = self.component_keys();
+        let storage: *mut Self = self;
+        // every key is visited once, so the components of different items never alias
+        keys.into_iter().filter_map(move |key| {
+            Some((
+                key,
+                unsafe { &mut *storage }.query_at_mut(key, with, without)?,
+            ))
+        })
+    }
+}
+
+impl ComponentStorage for ComponentBundle {
+    type Key = ();
+    type Stored = Box<dyn Component>;
+
+    fn component_keys(&self) -> Vec<()> {
+        if self.is_empty() { vec![] } else { vec![()] }
+    }
+
+    fn contains_key(&self, _key: ()) -> bool {
+        !self.is_empty()
+    }
+
+    fn component_at(&self, _key: (), component_id: ComponentId) -> Option<&Box<dyn Component>> {
+        self.get_component(component_id)
+    }
+
+    fn component_at_mut(
+        &mut self,
+        _key: (),
+        component_id: ComponentId,
+    ) -> Option<&mut Box<dyn Component>> {
+        self.get_component_mut(component_id)
+    }
+}
+
+impl ComponentStorage for SerializableComponentBundle {
+    type Key = ();
+    type Stored = Box<dyn SerializableComponent>;
+
+    fn component_keys(&self) -> Vec<()> {
+        if self.is_empty() { vec![] } else { vec![()] }
+    }
+
+    fn contains_key(&self, _key: ()) -> bool {
+        !self.is_empty()
+    }
+
+    fn component_at(
+        &self,
+        _key: (),
+        component_id: ComponentId,
+    ) -> Option<&Box<dyn SerializableComponent>> {
+        self.get_component(component_id)
+    }
+
+    fn component_at_mut(
+        &mut self,
+        _key: (),
+        component_id: ComponentId,
+    ) -> Option<&mut Box<dyn SerializableComponent>> {
+        self.get_component_mut(component_id)
+    }
+}
+
+/// A collection of bundles, keyed by index.
+impl<S: ComponentStorage<Key = ()>> ComponentStorage for [S] {
+    type Key = usize;
+    type Stored = S::Stored;
+
+    fn component_keys(&self) -> Vec<usize> {
+        (0..self.len())
+            .filter(|&index| self[index].contains_key(()))
+            .collect()
+    }
+
+    fn contains_key(&self, key: usize) -> bool {
+        self.get(key)
+            .is_some_and(|storage| storage.contains_key(()))
+    }
+
+    fn component_at(&self, key: usize, component_id: ComponentId) -> Option<&S::Stored> {
+        self.get(key)?.component_at((), component_id)
+    }
+
+    fn component_at_mut(
+        &mut self,
+        key: usize,
+        component_id: ComponentId,
+    ) -> Option<&mut S::Stored> {
+        self.get_mut(key)?.component_at_mut((), component_id)
+    }
+}
+
+impl<S: ComponentStorage<Key = ()>> ComponentStorage for Vec<S> {
+    type Key = usize;
+    type Stored = S::Stored;
+
+    fn component_keys(&self) -> Vec<usize> {
+        self.as_slice().component_keys()
+    }
+
+    fn contains_key(&self, key: usize) -> bool {
+        self.as_slice().contains_key(key)
+    }
+
+    fn component_at(&self, key: usize, component_id: ComponentId) -> Option<&S::Stored> {
+        self.as_slice().component_at(key, component_id)
+    }
+
+    fn component_at_mut(
+        &mut self,
+        key: usize,
+        component_id: ComponentId,
+    ) -> Option<&mut S::Stored> {
+        self.as_mut_slice().component_at_mut(key, component_id)
+    }
+}
+
+/// Typed [`ComponentStorage::query_all`], yielding the key and a tuple of component references for every match. Works
+/// on a [`World`](crate::world::World), a bundle, or a slice or [`Vec`] of bundles.
+#[macro_export]
+macro_rules! query_storage {
+    ($storage:expr, ($($with:ty),*), ($($without:ty),*)) => {
+        ::paste::paste! {
+            {
+                use hydrogen::ecs::component::ComponentStorage as _;
+                $storage.query_all([$(<$with>::COMPONENT_ID),*], [$(<$without>::COMPONENT_ID),*]).map(|(key, [$([<$with:snake>]),*])| {
+                    unsafe { (key, ($(&*(hydrogen::ecs::component::StoredComponent::as_component([<$with:snake>]) as *const dyn hydrogen::ecs::component::Component as *const $with),)*)) }
+                })
             }
         }
+    };
+    ($storage:expr, $($with:ty),*) => {
+        hydrogen::ecs::component::query_storage!($storage, ($($with),*), ())
+    };
+}
 
-        let mut component_slots: [Option<&mut Box<dyn SerializableComponent>>; WITH] =
-            array::from_fn(|_| None);
-        for (index, slot) in component_slots.iter_mut().enumerate() {
-            // ew
-            unsafe {
-                *slot = Some(
-                    ((self.get_component(with[index])?) as *const Box<dyn SerializableComponent>
-                        as *mut Box<dyn SerializableComponent>)
-                        .as_mut()?,
-                )
+#[macro_export]
+macro_rules! query_storage_mut {
+    ($storage:expr, ($($with:ty),*), ($($without:ty),*)) => {
+        ::paste::paste! {
+            {
+                use hydrogen::ecs::component::ComponentStorage as _;
+                $storage.query_all_mut([$(<$with>::COMPONENT_ID),*], [$(<$without>::COMPONENT_ID),*]).map(|(key, [$([<$with:snake>]),*])| {
+                    unsafe { (key, ($(&mut *(hydrogen::ecs::component::StoredComponent::as_component_mut([<$with:snake>]) as *mut dyn hydrogen::ecs::component::Component as *mut $with),)*)) }
+                })
             }
         }
-
-        Some(array::from_fn(|index| {
-            component_slots[index].take().unwrap()
-        }))
-    }
+    };
+    ($storage:expr, $($with:ty),*) => {
+        hydrogen::ecs::component::query_storage_mut!($storage, ($($with),*), ())
+    };
 }
 
 #[macro_export]
 macro_rules! query_bundle {
     ($bundle:expr, ($($with:ty),*), ($($without:ty),*)) => {
         ::paste::paste! {
-            $bundle.query([$(<$with>::COMPONENT_ID),*], [$(<$without>::COMPONENT_ID),*]).map(|[$([<$with:snake>]),*]| {
-                unsafe { ($(([<$with:snake>] as *const ::std::boxed::Box<dyn hydrogen_ecs::component::Component> as *const ::std::boxed::Box<$with>).as_ref().unwrap().as_ref(),)*) }
-            })
+            {
+                use hydrogen::ecs::component::ComponentStorage as _;
+                $bundle.query_at((), [$(<$with>::COMPONENT_ID),*], [$(<$without>::COMPONENT_ID),*]).map(|[$([<$with:snake>]),*]| {
+                    unsafe { ($(&*(hydrogen::ecs::component::StoredComponent::as_component([<$with:snake>]) as *const dyn hydrogen::ecs::component::Component as *const $with),)*) }
+                })
+            }
         }
     };
     ($bundle:expr, $($with:ty),*) => {
-        hydrogen_ecs::component::query!($bundle, ($($with),*), ())
+        hydrogen::ecs::component::query_bundle!($bundle, ($($with),*), ())
     };
 }
 
@@ -439,12 +789,108 @@ macro_rules! query_bundle {
 macro_rules! query_bundle_mut {
     ($bundle:expr, ($($with:ty),*), ($($without:ty),*)) => {
         ::paste::paste! {
-            $bundle.query_mut([$(<$with>::COMPONENT_ID),*], [$(<$without>::COMPONENT_ID),*]).map(|[$([<$with:snake>]),*]| {
-                unsafe { ($(([<$with:snake>] as *const ::std::boxed::Box<dyn hydrogen_ecs::component::Component> as *mut ::std::boxed::Box<$with>).as_mut().unwrap().as_mut(),)*) }
-            })
+            {
+                use hydrogen::ecs::component::ComponentStorage as _;
+                $bundle.query_at_mut((), [$(<$with>::COMPONENT_ID),*], [$(<$without>::COMPONENT_ID),*]).map(|[$([<$with:snake>]),*]| {
+                    unsafe { ($(&mut *(hydrogen::ecs::component::StoredComponent::as_component_mut([<$with:snake>]) as *mut dyn hydrogen::ecs::component::Component as *mut $with),)*) }
+                })
+            }
         }
     };
     ($bundle:expr, $($with:ty),*) => {
-        hydrogen_ecs::component::query_mut!($bundle, ($($with),*), ())
+        hydrogen::ecs::component::query_bundle_mut!($bundle, ($($with),*), ())
     };
 }
+
+pub use {query_bundle, query_bundle_mut, query_storage, query_storage_mut};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Component)]
+    struct StoredHealth(i32);
+
+    #[derive(Debug, Clone, PartialEq, Component)]
+    struct BundledArmor(i32);
+
+    #[derive(Debug, Clone, PartialEq, Component)]
+    struct BundledTag;
+
+    #[derive(Bundle)]
+    struct Soldier {
+        health: StoredHealth,
+        armor: BundledArmor,
+    }
+
+    #[derive(Bundle)]
+    struct Marker(BundledTag);
+
+    #[test]
+    #[should_panic(expected = "queried mutably more than once")]
+    fn mutable_queries_refuse_duplicate_components() {
+        let mut bundle = ComponentBundle::new();
+        bundle.set_component(StoredHealth(1));
+        let _ = bundle.query_mut([StoredHealth::COMPONENT_ID, StoredHealth::COMPONENT_ID], []);
+    }
+
+    #[test]
+    #[should_panic(expected = "queried mutably more than once")]
+    fn mutable_world_queries_refuse_duplicate_components() {
+        let mut world = crate::world::World::default();
+        let entity_id = world.spawn((StoredHealth(1),));
+        let _ = world.query_one_mut(
+            entity_id,
+            [StoredHealth::COMPONENT_ID, StoredHealth::COMPONENT_ID],
+            [],
+        );
+    }
+
+    #[test]
+    fn derived_bundles_spawn_query_and_take() {
+        let mut world = crate::world::World::default();
+        let soldier = world.spawn(Soldier {
+            health: StoredHealth(10),
+            armor: BundledArmor(3),
+        });
+        let other = world.spawn(Soldier {
+            health: StoredHealth(4),
+            armor: BundledArmor(0),
+        });
+        world.insert_bundle(other, Marker(BundledTag));
+
+        let found = query_storage!(world, (StoredHealth, BundledArmor), (BundledTag))
+            .map(|(entity_id, (health, armor))| (entity_id, health.0, armor.0))
+            .collect::<Vec<_>>();
+        assert_eq!(found, [(soldier, 10, 3)]);
+        let tagged = query_storage!(world, BundledTag)
+            .map(|(entity_id, _)| entity_id)
+            .collect::<Vec<_>>();
+        assert_eq!(tagged, [other]);
+
+        let bundle = world.take_bundle(other);
+        assert_eq!(bundle.len(), 3);
+        assert_eq!(
+            query_bundle!(bundle, StoredHealth, BundledArmor, BundledTag),
+            Some((&StoredHealth(4), &BundledArmor(0), &BundledTag))
+        );
+        assert!(!world.has_entity(other));
+
+        // a taken bundle can be spawned again as it is
+        let respawned = world.spawn(bundle);
+        assert_eq!(
+            query_storage!(world, BundledTag)
+                .map(|(entity_id, _)| entity_id)
+                .collect::<Vec<_>>(),
+            [respawned]
+        );
+        assert_eq!(
+            StoredHealth::query_one(&world, respawned),
+            Some(&StoredHealth(4))
+        );
+    }
+}
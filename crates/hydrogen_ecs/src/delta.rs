@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::component::{ComponentId, SerializableComponent};
+
+/// Runs of changed bytes separated by fewer unchanged bytes than this are merged, since a new run costs about as much
+/// as a couple of bytes.
+const MERGE_GAP: usize = 3;
+/// How far ahead to look for where two values line up again after they start to differ.
+const RESYNC_WINDOW: usize = 32;
+/// How many bytes have to match for two values to count as lined up again.
+const RESYNC_LEN: usize = 4;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DeltaError {
+    #[error("delta for component {0:?} was made against a different value")]
+    BaseMismatch(ComponentId),
+    #[error("delta for component {0:?} is out of bounds")]
+    OutOfBounds(ComponentId),
+    #[error("postcard error: {0}")]
+    Postcard(#[from] postcard::Error),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct DeltaRun {
+    /// The number of bytes to keep from the base since the end of the previous run.
+    skip: u32,
+    /// The number of bytes of the base that `bytes` replaces.
+    remove: u32,
+    bytes: Vec<u8>,
+}
+
+/// A byte-level patch from one value of a [`SerializableComponent`] to another, made by comparing their serialized
+/// forms. Works best for large components where only a few fields change at once.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ComponentDelta {
+    pub component_id: ComponentId,
+    /// Checksum of the serialized value the delta was made against, so that a receiver with a different value can
+    /// tell it needs the whole component instead.
+    base_checksum: u64,
+    runs: Vec<DeltaRun>,
+}
+
+/// FNV-1a, which is the same on every platform and build unlike the standard library's hasher.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The shortest combination of skipped old and new bytes after which the two line up again, or `None` if they don't
+/// within [`RESYNC_WINDOW`].
+fn resync(old: &[u8], new: &[u8]) -> Option<(usize, usize)> {
+    for distance in 1..=RESYNC_WINDOW * 2 {
+        for old_skip in distance.saturating_sub(RESYNC_WINDOW)..=distance.min(RESYNC_WINDOW) {
+            let new_skip = distance - old_skip;
+            let (Some(old_rest), Some(new_rest)) = (old.get(old_skip..), new.get(new_skip..))
+            else {
+                continue;
+            };
+
+            let both_ended = old_rest.is_empty() && new_rest.is_empty();
+            let lined_up = old_rest.len() >= RESYNC_LEN
+                && new_rest.len() >= RESYNC_LEN
+                && old_rest[..RESYNC_LEN] == new_rest[..RESYNC_LEN];
+            if both_ended || lined_up {
+                return Some((old_skip, new_skip));
+            }
+        }
+    }
+
+    None
+}
+
+impl ComponentDelta {
+    /// The delta from `old` to `new`, or `None` if it wouldn't be smaller than `new` itself, e.g. because most of the
+    /// component changed.
+    pub fn between(
+        old: &dyn SerializableComponent,
+        new: &dyn SerializableComponent,
+    ) -> Result<Option<Self>, DeltaError> {
+        let old_bytes = postcard::to_allocvec(old)?;
+        let new_bytes = postcard::to_allocvec(new)?;
+
+        let mut runs = Vec::<DeltaRun>::new();
+        let (mut old_index, mut new_index) = (0, 0);
+        let mut skip = 0;
+        while old_index < old_bytes.len() || new_index < new_bytes.len() {
+            if old_index < old_bytes.len()
+                && new_index < new_bytes.len()
+                && old_bytes[old_index] == new_bytes[new_index]
+            {
+                old_index += 1;
+                new_index += 1;
+                skip += 1;
+                continue;
+            }
+
+            let (remove, insert) = resync(&old_bytes[old_index..], &new_bytes[new_index..])
+                .unwrap_or((old_bytes.len() - old_index, new_bytes.len() - new_index));
+            let inserted = &new_bytes[new_index..new_index + insert];
+
+            match runs.last_mut() {
+                Some(run) if skip < MERGE_GAP => {
+                    run.remove += (skip + remove) as u32;
+                    run.bytes
+                        .extend_from_slice(&new_bytes[new_index - skip..new_index]);
+                    run.bytes.extend_from_slice(inserted);
+                }
+                _ => runs.push(DeltaRun {
+                    skip: skip as u32,
+                    remove: remove as u32,
+                    bytes: inserted.to_vec(),
+                }),
+            }
+
+            old_index += remove;
+            new_index += insert;
+            skip = 0;
+        }
+
+        let delta = Self {
+            component_id: new.component_id(),
+            base_checksum: checksum(&old_bytes),
+            runs,
+        };
+
+        if postcard::experimental::serialized_size(&delta)? >= new_bytes.len() {
+            return Ok(None);
+        }
+
+        Ok(Some(delta))
+    }
+
+    /// Applies the delta to `base`, which must be the value it was made against.
+    pub fn apply(
+        &self,
+        base: &dyn SerializableComponent,
+    ) -> Result<Box<dyn SerializableComponent>, DeltaError> {
+        let base_bytes = postcard::to_allocvec(base)?;
+        if checksum(&base_bytes) != self.base_checksum {
+            return Err(DeltaError::BaseMismatch(self.component_id));
+        }
+
+        let mut bytes = Vec::with_capacity(base_bytes.len());
+        let mut position = 0;
+        for run in self.runs.iter() {
+            let kept_end = position + run.skip as usize;
+            bytes.extend_from_slice(
+                base_bytes
+                    .get(position..kept_end)
+                    .ok_or(DeltaError::OutOfBounds(self.component_id))?,
+            );
+            bytes.extend_from_slice(&run.bytes);
+            position = kept_end + run.remove as usize;
+        }
+        bytes.extend_from_slice(
+            base_bytes
+                .get(position..)
+                .ok_or(DeltaError::OutOfBounds(self.component_id))?,
+        );
+
+        Ok(postcard::from_bytes(&bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ecs_net::{NetEcsCommand, ServerEntityId},
+        entity::EntityId,
+        world::World,
+    };
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct DeltaInventory {
+        owner: String,
+        slots: Vec<u32>,
+        gold: u32,
+    }
+
+    fn inventory() -> DeltaInventory {
+        DeltaInventory {
+            owner: "player".to_string(),
+            slots: (0..64).map(|slot| slot * 1000).collect(),
+            gold: 100,
+        }
+    }
+
+    #[test]
+    fn deltas_are_smaller_than_the_whole_component() {
+        let old = inventory();
+        let mut new = old.clone();
+        new.slots[40] = 7;
+        new.gold = 250;
+
+        let delta = ComponentDelta::between(&old, &new).unwrap().unwrap();
+        let applied = delta.apply(&old).unwrap();
+        assert_eq!(applied.downcast_ref::<DeltaInventory>(), Some(&new));
+
+        let server_entity_id = ServerEntityId(EntityId(0));
+        let full_len = NetEcsCommand::SetComponent(server_entity_id, Box::new(new)).encoded_len();
+        let delta_len = NetEcsCommand::SetComponentDelta(server_entity_id, delta).encoded_len();
+        assert!(
+            delta_len * 4 < full_len,
+            "delta is {delta_len} bytes, the whole component is {full_len}"
+        );
+    }
+
+    #[test]
+    fn deltas_handle_values_changing_size() {
+        let old = inventory();
+        let mut new = old.clone();
+        new.owner = "a much longer name".to_string();
+        new.slots.truncate(60);
+
+        let delta = ComponentDelta::between(&old, &new).unwrap().unwrap();
+        let applied = delta.apply(&old).unwrap();
+        assert_eq!(applied.downcast_ref::<DeltaInventory>(), Some(&new));
+    }
+
+    #[test]
+    fn mismatched_bases_request_the_whole_component() {
+        let old = inventory();
+        let mut new = old.clone();
+        new.gold = 250;
+        let delta = ComponentDelta::between(&old, &new).unwrap().unwrap();
+
+        let mut stale = old.clone();
+        stale.gold = 99;
+        assert_eq!(
+            delta.apply(&stale).unwrap_err(),
+            DeltaError::BaseMismatch(DeltaInventory::COMPONENT_ID)
+        );
+
+        // a client that missed an update keeps its value and asks for the whole component
+        let server_entity_id = ServerEntityId(EntityId(0));
+        let mut client = World::default();
+        client
+            .execute_net_command(NetEcsCommand::SetComponent(
+                server_entity_id,
+                Box::new(stale.clone()),
+            ))
+            .unwrap();
+        client
+            .execute_net_command(NetEcsCommand::SetComponentDelta(server_entity_id, delta))
+            .unwrap();
+
+        let entity_id = client.entity_id_from_server(server_entity_id);
+        assert_eq!(DeltaInventory::query_one(&client, entity_id), Some(&stale));
+        assert_eq!(
+            client.net_queues().outgoing_requests,
+            [(server_entity_id, DeltaInventory::COMPONENT_ID)]
+        );
+    }
+}
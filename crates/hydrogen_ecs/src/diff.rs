@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    change_tracker::ComponentTrackerEvent,
+    component::{ComponentId, SerializableComponentBundle},
+    entity::EntityId,
+    migration::MigrationError,
+    snapshot::WorldSnapshot,
+    world::World,
+};
+
+/// The changes to the serializable components and resources of a [`World`] between two points in time. An entity
+/// counts as existing if it has at least one serializable component.
+///
+/// Every event keeps the old value as well as the new one, so a diff can be undone with [`WorldDiff::inverse`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct WorldDiff {
+    pub added_entities: BTreeSet<EntityId>,
+    pub removed_entities: BTreeSet<EntityId>,
+    /// Includes the components of added and removed entities.
+    pub components: BTreeMap<EntityId, BTreeMap<ComponentId, ComponentTrackerEvent>>,
+    pub resources: BTreeMap<ComponentId, ComponentTrackerEvent>,
+}
+
+fn diff_bundles(
+    old: Option<&SerializableComponentBundle>,
+    new: Option<&SerializableComponentBundle>,
+) -> BTreeMap<ComponentId, ComponentTrackerEvent> {
+    let empty = SerializableComponentBundle::new();
+    let old = old.unwrap_or(&empty);
+    let new = new.unwrap_or(&empty);
+
+    let component_ids: BTreeSet<ComponentId> = old
+        .iter()
+        .chain(new.iter())
+        .map(|(component_id, _)| component_id)
+        .collect();
+
+    component_ids
+        .into_iter()
+        .filter_map(|component_id| {
+            let event = match (
+                old.get_component(component_id),
+                new.get_component(component_id),
+            ) {
+                (None, Some(new)) => ComponentTrackerEvent::Added(new.clone()),
+                (Some(old), None) => ComponentTrackerEvent::Removed(old.clone()),
+                (Some(old), Some(new)) if old != new => ComponentTrackerEvent::Changed {
+                    old: old.clone(),
+                    new: new.clone(),
+                },
+                _ => return None,
+            };
+            Some((component_id, event))
+        })
+        .collect()
+}
+
+impl WorldDiff {
+    /// The changes needed to turn `old` into `new`.
+    pub fn between(old: &WorldSnapshot, new: &WorldSnapshot) -> Self {
+        let mut diff = Self::default();
+
+        let entity_ids: BTreeSet<EntityId> = old
+            .entities
+            .keys()
+            .chain(new.entities.keys())
+            .copied()
+            .collect();
+        for entity_id in entity_ids {
+            let old_bundle = old.entities.get(&entity_id);
+            let new_bundle = new.entities.get(&entity_id);
+
+            match (old_bundle, new_bundle) {
+                (None, Some(_)) => {
+                    diff.added_entities.insert(entity_id);
+                }
+                (Some(_), None) => {
+                    diff.removed_entities.insert(entity_id);
+                }
+                _ => {}
+            }
+
+            let events = diff_bundles(old_bundle, new_bundle);
+            if !events.is_empty() {
+                diff.components.insert(entity_id, events);
+            }
+        }
+
+        diff.resources = diff_bundles(Some(&old.resources), Some(&new.resources));
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_entities.is_empty()
+            && self.removed_entities.is_empty()
+            && self.components.is_empty()
+            && self.resources.is_empty()
+    }
+
+    /// A diff that undoes this one.
+    pub fn inverse(&self) -> Self {
+        let invert = |event: &ComponentTrackerEvent| match event {
+            ComponentTrackerEvent::Added(new) => ComponentTrackerEvent::Removed(new.clone()),
+            ComponentTrackerEvent::Changed { old, new } => ComponentTrackerEvent::Changed {
+                old: new.clone(),
+                new: old.clone(),
+            },
+            ComponentTrackerEvent::Removed(old) => ComponentTrackerEvent::Added(old.clone()),
+        };
+
+        Self {
+            added_entities: self.removed_entities.clone(),
+            removed_entities: self.added_entities.clone(),
+            components: self
+                .components
+                .iter()
+                .map(|(&entity_id, events)| {
+                    let events = events
+                        .iter()
+                        .map(|(&component_id, event)| (component_id, invert(event)))
+                        .collect();
+                    (entity_id, events)
+                })
+                .collect(),
+            resources: self
+                .resources
+                .iter()
+                .map(|(&component_id, event)| (component_id, invert(event)))
+                .collect(),
+        }
+    }
+}
+
+impl World {
+    /// The changes needed to turn `other` into this world.
+    pub fn diff(&self, other: &World) -> WorldDiff {
+        WorldDiff::between(&other.snapshot(), &self.snapshot())
+    }
+
+    /// The changes made to this world since `snapshot` was taken.
+    pub fn diff_since(&self, snapshot: &WorldSnapshot) -> WorldDiff {
+        WorldDiff::between(snapshot, &self.snapshot())
+    }
+
+    /// Applies the new values of `diff` without checking the world's current values against its old ones. Components
+    /// from older versions are upgraded with the world's [`migrations`](World::migrations).
+    ///
+    /// Returns the errors of any components that couldn't be migrated, which are left out of the world.
+    pub fn apply_diff(&mut self, diff: WorldDiff) -> Vec<MigrationError> {
+        let mut errors = Vec::new();
+
+        for &entity_id in diff.removed_entities.iter() {
+            self.delete_entity(entity_id);
+        }
+
+        if let Some(last_added) = diff.added_entities.last() {
+            self.ensure_next_entity_id(last_added.0 + 1);
+        }
+
+        for (entity_id, events) in diff.components {
+            if diff.removed_entities.contains(&entity_id) {
+                continue;
+            }
+
+            for (component_id, event) in events {
+                match event {
+                    ComponentTrackerEvent::Added(new)
+                    | ComponentTrackerEvent::Changed { new, .. } => {
+                        match self.migrations().migrate(new) {
+                            Ok(new) => {
+                                self.set_component_boxed(entity_id, new);
+                            }
+                            Err(e) => errors.push(e),
+                        }
+                    }
+                    ComponentTrackerEvent::Removed(_) => {
+                        self.delete_component(entity_id, component_id);
+                    }
+                }
+            }
+        }
+
+        for (component_id, event) in diff.resources {
+            match event {
+                ComponentTrackerEvent::Added(new) | ComponentTrackerEvent::Changed { new, .. } => {
+                    match self.migrations().migrate(new) {
+                        Ok(new) => {
+                            self.insert_resource_boxed(new);
+                        }
+                        Err(e) => errors.push(e),
+                    }
+                }
+                ComponentTrackerEvent::Removed(_) => {
+                    self.remove_resource_boxed(component_id);
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::SerializableComponent;
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct DiffHealth(u32);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct DiffArmor(u32);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct DiffWeather(u32);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct DiffClock(u64);
+
+    fn assert_same(world: &World, snapshot: &WorldSnapshot) {
+        let actual = world.snapshot();
+        assert_eq!(actual.entities, snapshot.entities);
+        assert_eq!(actual.resources, snapshot.resources);
+    }
+
+    #[test]
+    fn applying_a_diff_and_its_inverse() {
+        let mut world = World::default();
+        let player = world.spawn((DiffHealth(10), DiffArmor(2)));
+        let enemy = world.spawn((DiffHealth(3),));
+        let wall = world.spawn((DiffArmor(50),));
+        world.insert_resource(DiffClock(0));
+        world.insert_resource(DiffWeather(1));
+        let old = world.snapshot();
+
+        DiffHealth::query_one_mut(&mut world, player).unwrap().0 = 7;
+        world.delete_component(player, DiffArmor::COMPONENT_ID);
+        world.set_component(wall, DiffHealth(100));
+        world.delete_entity(enemy);
+        let ally = world.spawn((DiffHealth(5), DiffArmor(1)));
+        world.insert_resource(DiffClock(60));
+        world.remove_resource_boxed(DiffWeather::COMPONENT_ID);
+        let new = world.snapshot();
+
+        let diff = WorldDiff::between(&old, &new);
+        assert_eq!(diff.added_entities, BTreeSet::from([ally]));
+        assert_eq!(diff.removed_entities, BTreeSet::from([enemy]));
+        assert_eq!(
+            diff.components.keys().copied().collect::<Vec<_>>(),
+            [player, enemy, wall, ally]
+        );
+        assert!(matches!(
+            diff.components[&player][&DiffHealth::COMPONENT_ID],
+            ComponentTrackerEvent::Changed { .. }
+        ));
+        assert!(matches!(
+            diff.components[&player][&DiffArmor::COMPONENT_ID],
+            ComponentTrackerEvent::Removed(_)
+        ));
+        assert!(matches!(
+            diff.components[&wall][&DiffHealth::COMPONENT_ID],
+            ComponentTrackerEvent::Added(_)
+        ));
+        assert!(!diff.components[&wall].contains_key(&DiffArmor::COMPONENT_ID));
+        assert!(matches!(
+            diff.resources[&DiffWeather::COMPONENT_ID],
+            ComponentTrackerEvent::Removed(_)
+        ));
+        assert_eq!(world.diff_since(&old), diff);
+        assert!(world.diff_since(&new).is_empty());
+
+        let mut world = World::default();
+        assert!(world.restore(old.clone()).is_empty());
+        assert!(world.apply_diff(diff.clone()).is_empty());
+        assert_same(&world, &new);
+        assert_eq!(world.peek_next_entity_id(), EntityId(new.next_entity_id));
+
+        assert!(world.apply_diff(diff.inverse()).is_empty());
+        assert_same(&world, &old);
+        assert_eq!(diff.inverse().inverse(), diff);
+    }
+}
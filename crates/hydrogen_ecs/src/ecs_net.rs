@@ -1,7 +1,12 @@
-use std::collections::BTreeMap;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+    fmt, mem,
+    time::{Duration, Instant},
+};
 
 use derive_more::*;
-use hydrogen_core::dyn_util::AsAny;
+use hydrogen_core::events::{EventReceiver, EventSender};
 use hydrogen_data_structures::selection::Selection;
 use hydrogen_net::{
     comm::{NetMessage, TcpCommunicator},
@@ -10,9 +15,18 @@ use hydrogen_net::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    component::{Component, ComponentId, SerializableComponent},
+    component::{
+        Component, ComponentId, ComponentType, SerializableComponent, SerializableComponentBundle,
+    },
+    delta::ComponentDelta,
     entity::EntityId,
+    hierarchy::{Children, Parent},
+    interest::Interest,
+    naming,
+    ownership::{Ownership, OwnershipSeq},
+    prediction::{InputTick, LastProcessedInput},
     query, query_one,
+    rpc::EntityRpc,
     world::World,
 };
 
@@ -22,7 +36,6 @@ mod hydrogen {
 }
 
 #[derive(
-    Debug,
     Clone,
     Copy,
     Serialize,
@@ -40,6 +53,14 @@ mod hydrogen {
 )]
 pub struct ServerEntityId(pub EntityId);
 
+impl fmt::Debug for ServerEntityId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ServerEntityId")
+            .field(&fmt::from_fn(|f| naming::fmt_entity_id(f, self.0, true)))
+            .finish()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, SerializableComponent)]
 pub struct Replicate {
     pub server_entity_id: ServerEntityId,
@@ -51,28 +72,184 @@ pub struct Replicate {
     pub auto_replicate_changes: Selection<ComponentId>,
 }
 
+impl Replicate {
+    /// Whether a component of the entity is sent to clients. [`Children`] never is, since each world builds its own
+    /// from the [`Parent`] components it has.
+    pub fn replicates(&self, component_id: ComponentId) -> bool {
+        component_id == Replicate::COMPONENT_ID
+            || (component_id != Children::COMPONENT_ID
+                && self.replicated_components.contains(&component_id))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, NetMessage, IsVariant, Unwrap, TryUnwrap)]
 pub enum NetEcsCommand {
     SetComponent(ServerEntityId, Box<dyn SerializableComponent>),
+    /// Changes a component the receiver already has. See [`EcsReplicator::delta_compression`].
+    SetComponentDelta(ServerEntityId, ComponentDelta),
+    /// Sent by a client whose value of a component didn't match a [`NetEcsCommand::SetComponentDelta`], asking the
+    /// server to send the whole component again.
+    RequestComponent(ServerEntityId, ComponentId),
     DeleteComponent(ServerEntityId, ComponentId),
+    /// An input for an entity owned by the client, see [`Prediction`](crate::prediction::Prediction).
+    Input(ServerEntityId, InputTick, Box<dyn SerializableComponent>),
+    /// The last input for an entity that the server has processed, sent to its owner after the entity's state.
+    InputAck(ServerEntityId, InputTick),
+    /// A one-off message about an entity, see [`World::send_rpc`].
+    Rpc(ServerEntityId, Box<dyn SerializableComponent>),
+    /// Creates an entity on the client with all the components it's initially sent, so that it's never seen half
+    /// constructed.
+    SpawnEntity(ServerEntityId, SerializableComponentBundle),
     DeleteEntity(ServerEntityId),
+    /// Sent by the server after [`World::transfer_ownership`], to every client that has the entity.
+    TransferOwnership(ServerEntityId, Option<ClientId>, OwnershipSeq),
+    /// Sent by the new owner of an entity once it has taken over from the server's state.
+    AcceptOwnership(ServerEntityId, OwnershipSeq),
+    /// Every command from one server update, applied together: nothing in it is applied if any of its components
+    /// can't be migrated. Only ever sent by the server. See [`EcsReplicator::batch_updates`].
+    Batch(Vec<NetEcsCommand>),
+    SetResource(Box<dyn SerializableComponent>),
+    DeleteResource(ComponentId),
 }
 
 impl NetEcsCommand {
-    pub fn server_entity_id(&self) -> ServerEntityId {
+    /// Returns `None` for commands that don't target an entity, such as resource commands.
+    pub fn server_entity_id(&self) -> Option<ServerEntityId> {
         match self {
-            Self::SetComponent(server_entity_id, _) => *server_entity_id,
-            Self::DeleteComponent(server_entity_id, _) => *server_entity_id,
-            Self::DeleteEntity(server_entity_id) => *server_entity_id,
+            Self::SetComponent(server_entity_id, _) => Some(*server_entity_id),
+            Self::SetComponentDelta(server_entity_id, _) => Some(*server_entity_id),
+            Self::RequestComponent(server_entity_id, _) => Some(*server_entity_id),
+            Self::DeleteComponent(server_entity_id, _) => Some(*server_entity_id),
+            Self::Input(server_entity_id, ..) => Some(*server_entity_id),
+            Self::InputAck(server_entity_id, _) => Some(*server_entity_id),
+            Self::Rpc(server_entity_id, _) => Some(*server_entity_id),
+            Self::SpawnEntity(server_entity_id, _) => Some(*server_entity_id),
+            Self::DeleteEntity(server_entity_id) => Some(*server_entity_id),
+            Self::TransferOwnership(server_entity_id, ..) => Some(*server_entity_id),
+            Self::AcceptOwnership(server_entity_id, _) => Some(*server_entity_id),
+            Self::Batch(_) | Self::SetResource(_) | Self::DeleteResource(_) => None,
         }
     }
+
+    /// The size of the command when serialized on its own, close to what it costs to send.
+    pub fn encoded_len(&self) -> usize {
+        postcard::experimental::serialized_size(self).unwrap_or(0)
+    }
+}
+
+pub(crate) type ClientWrite = (ServerEntityId, Box<dyn SerializableComponent>);
+
+/// Things sent or received by a world that are waiting to be dealt with by an [`EcsReplicator`],
+/// [`World::process_inputs`] or [`World::take_rpcs`].
+#[derive(Debug, Default)]
+pub(crate) struct NetQueues {
+    /// Made by a client world with [`NetEcsCommand::RequestComponent`], waiting to be sent to the server.
+    pub(crate) outgoing_requests: Vec<(ServerEntityId, ComponentId)>,
+    /// Received by the server world, waiting to be resent by the replicator of the client that asked.
+    pub(crate) incoming_requests: BTreeMap<ClientId, Vec<(ServerEntityId, ComponentId)>>,
+    /// Components written by clients, so that their replicators know not to send them back.
+    pub(crate) client_writes: BTreeMap<ClientId, Vec<ClientWrite>>,
+    /// Received by the server world from the owners of entities.
+    pub(crate) inputs: Vec<(ServerEntityId, InputTick, Box<dyn SerializableComponent>)>,
+    /// Sent by the server world, received separately by the replicator of every client.
+    pub(crate) rpcs: EventSender<EntityRpc>,
+    /// Received by a client world, waiting for [`World::take_rpcs`].
+    pub(crate) incoming_rpcs: Vec<(EntityId, Box<dyn SerializableComponent>)>,
+    /// Received by a client world, waiting for its replicator to take over or let go of the entities.
+    pub(crate) ownership_transfers: Vec<(ServerEntityId, Option<ClientId>, OwnershipSeq)>,
 }
 
-#[derive(Debug, PartialEq)]
+/// How a type of component is replicated by an [`EcsReplicator`]. Components without a rule have a priority of 0 and
+/// no rate limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplicationRule {
+    /// Changes with a higher priority are sent first when the replicator's
+    /// [`byte_budget`](EcsReplicator::byte_budget) runs out.
+    pub priority: i32,
+    /// The most times per second that changes are sent, or `None` to send every change. A component's first value is
+    /// always sent straight away.
+    pub max_rate: Option<f32>,
+}
+
+/// Added to the priority of every component of an entity, see [`ReplicationRule::priority`]. Only used by the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Component)]
+pub struct ReplicationPriority(pub i32);
+
+/// A component value waiting to be sent by [`EcsReplicator::server_update`].
+struct PendingSet {
+    server_entity_id: ServerEntityId,
+    component_id: ComponentId,
+    priority: i64,
+}
+
+#[derive(Debug)]
 pub struct EcsReplicator {
     pub client_id: ClientId,
     pub current_entities:
         BTreeMap<ServerEntityId, BTreeMap<ComponentId, Box<dyn SerializableComponent>>>,
+    /// The world resources that are replicated to the client. Only serializable resources can be replicated.
+    pub replicated_resources: Selection<ComponentId>,
+    pub current_resources: BTreeMap<ComponentId, Box<dyn SerializableComponent>>,
+    /// Whether changed components are sent as a [`ComponentDelta`] against the value in `current_entities` when that's
+    /// smaller than the whole component. On by default.
+    pub delta_compression: bool,
+    /// Narrows down the entities in [`Replicate::replicate_to`] that this client is sent.
+    pub interest: Interest,
+    pub replication_rules: BTreeMap<ComponentId, ReplicationRule>,
+    /// The most bytes of commands to send per server update, or `None` for no limit. Component values that don't fit
+    /// are deferred to later updates, in order of priority. A deferred value gains 1 priority for every update it
+    /// waits so that everything is sent eventually, and at least one value is sent every update even if it's bigger
+    /// than the budget.
+    ///
+    /// Deletions, resources, new entities, RPCs and [`Replicate`] components are always sent straight away, but count
+    /// towards the budget.
+    pub byte_budget: Option<usize>,
+    /// Whether everything from one server update is sent as a single [`NetEcsCommand::Batch`], so that the client
+    /// never sees only some of the changes made at once. On by default.
+    pub batch_updates: bool,
+    last_sent: BTreeMap<(ServerEntityId, ComponentId), Instant>,
+    deferred_updates: BTreeMap<(ServerEntityId, ComponentId), u32>,
+    acked_inputs: BTreeMap<ServerEntityId, InputTick>,
+    /// Subscribed to the world's RPCs on the first server update.
+    rpcs: Option<EventReceiver<EntityRpc>>,
+    /// The last ownership transfer of each entity the client has been told about.
+    sent_ownership: BTreeMap<ServerEntityId, OwnershipSeq>,
+}
+
+/// Replicators are equal if they'd send the same things, so the RPC subscription isn't compared.
+impl PartialEq for EcsReplicator {
+    fn eq(&self, other: &Self) -> bool {
+        let Self {
+            client_id,
+            current_entities,
+            replicated_resources,
+            current_resources,
+            delta_compression,
+            interest,
+            replication_rules,
+            byte_budget,
+            batch_updates,
+            last_sent,
+            deferred_updates,
+            acked_inputs,
+            rpcs: _,
+            sent_ownership,
+        } = self;
+
+        *client_id == other.client_id
+            && *current_entities == other.current_entities
+            && *replicated_resources == other.replicated_resources
+            && *current_resources == other.current_resources
+            && *delta_compression == other.delta_compression
+            && *interest == other.interest
+            && *replication_rules == other.replication_rules
+            && *byte_budget == other.byte_budget
+            && *batch_updates == other.batch_updates
+            && *last_sent == other.last_sent
+            && *deferred_updates == other.deferred_updates
+            && *acked_inputs == other.acked_inputs
+            && *sent_ownership == other.sent_ownership
+    }
 }
 
 impl EcsReplicator {
@@ -80,73 +257,298 @@ impl EcsReplicator {
         Self {
             client_id,
             current_entities: Default::default(),
+            replicated_resources: Selection::none(),
+            current_resources: Default::default(),
+            delta_compression: true,
+            interest: Interest::All,
+            replication_rules: Default::default(),
+            byte_budget: None,
+            batch_updates: true,
+            last_sent: Default::default(),
+            deferred_updates: Default::default(),
+            acked_inputs: Default::default(),
+            rpcs: None,
+            sent_ownership: Default::default(),
+        }
+    }
+
+    pub fn set_replication_rule<T: ComponentType>(&mut self, rule: ReplicationRule) -> &mut Self {
+        self.replication_rules.insert(T::COMPONENT_ID, rule);
+        self
+    }
+
+    /// The number of component values that didn't fit in the last update's byte budget.
+    pub fn deferred_len(&self) -> usize {
+        self.deferred_updates.len()
+    }
+
+    /// The command that brings a client from `old` to `new`, which is a delta if possible.
+    fn set_component_command(
+        delta_compression: bool,
+        server_entity_id: ServerEntityId,
+        old: &dyn SerializableComponent,
+        new: &dyn SerializableComponent,
+    ) -> NetEcsCommand {
+        // a delta of a Parent would be applied to the client's own entity id, which differs from the server's
+        if delta_compression
+            && new.component_id() != Parent::COMPONENT_ID
+            && let Ok(Some(delta)) = ComponentDelta::between(old, new)
+        {
+            return NetEcsCommand::SetComponentDelta(server_entity_id, delta);
+        }
+
+        NetEcsCommand::SetComponent(server_entity_id, new.clone_box())
+    }
+
+    /// Queues a command to be sent at the end of the update, adding its size to `bytes_sent` if there's a budget to
+    /// keep track of.
+    fn send(
+        &self,
+        outgoing: &mut Vec<NetEcsCommand>,
+        bytes_sent: &mut usize,
+        command: NetEcsCommand,
+    ) {
+        if self.byte_budget.is_some() {
+            *bytes_sent += command.encoded_len();
+        }
+        outgoing.push(command);
+    }
+
+    /// A [`NetEcsCommand::SpawnEntity`] with every replicated component of an entity the client doesn't have yet,
+    /// along with the number of components in it.
+    fn spawn_command(
+        &mut self,
+        world: &World,
+        server_entity_id: ServerEntityId,
+        replicate: &Replicate,
+        now: Instant,
+    ) -> (NetEcsCommand, usize) {
+        let mut bundle = SerializableComponentBundle::new();
+        for (component_id, component) in world.get_all_serializable_components(server_entity_id.0) {
+            if replicate.replicates(component_id) {
+                bundle.set_component_boxed(component.clone_box());
+            }
+        }
+
+        let current_components = self.current_entities.entry(server_entity_id).or_default();
+        let mut len = 0;
+        for (component_id, component) in bundle.iter() {
+            current_components.insert(component_id, component.clone_box());
+            self.last_sent.insert((server_entity_id, component_id), now);
+            self.deferred_updates
+                .remove(&(server_entity_id, component_id));
+            len += 1;
+        }
+
+        (NetEcsCommand::SpawnEntity(server_entity_id, bundle), len)
+    }
+
+    fn flush(&self, comm: &mut TcpCommunicator, outgoing: Vec<NetEcsCommand>) {
+        if self.batch_updates && outgoing.len() > 1 {
+            comm.send(NetEcsCommand::Batch(outgoing));
+            return;
+        }
+
+        for command in outgoing {
+            comm.send(command);
         }
     }
 
+    fn server_update_resources(
+        &mut self,
+        world: &World,
+        outgoing: &mut Vec<NetEcsCommand>,
+        bytes_sent: &mut usize,
+    ) {
+        let mut commands = Vec::new();
+        self.current_resources.retain(|component_id, _| {
+            let should_exist = world.has_resource(*component_id)
+                && self.replicated_resources.contains(component_id);
+            if !should_exist {
+                commands.push(NetEcsCommand::DeleteResource(*component_id));
+            }
+            should_exist
+        });
+
+        for (component_id, resource) in world.serializable_resources() {
+            if !self.replicated_resources.contains(&component_id) {
+                continue;
+            }
+
+            if self
+                .current_resources
+                .get(&component_id)
+                .is_none_or(|current_resource| current_resource.as_ref() != resource)
+            {
+                self.current_resources
+                    .insert(component_id, resource.clone_box());
+                commands.push(NetEcsCommand::SetResource(resource.clone_box()));
+            }
+        }
+
+        for command in commands {
+            self.send(outgoing, bytes_sent, command);
+        }
+    }
+
+    /// Whether the client should have an entity, either because it owns it or because it's allowed to see it and
+    /// it's of interest.
+    fn is_visible(&self, world: &World, entity_id: EntityId, replicate: &Replicate) -> bool {
+        replicate.owner == Some(self.client_id)
+            || (replicate.replicate_to.contains(&self.client_id)
+                && self.interest.is_relevant(world, entity_id))
+    }
+
+    fn is_rate_limited(
+        &self,
+        now: Instant,
+        server_entity_id: ServerEntityId,
+        component_id: ComponentId,
+    ) -> bool {
+        let Some(max_rate) = self
+            .replication_rules
+            .get(&component_id)
+            .and_then(|rule| rule.max_rate)
+        else {
+            return false;
+        };
+
+        self.last_sent
+            .get(&(server_entity_id, component_id))
+            .is_some_and(|&last_sent| {
+                now.duration_since(last_sent) < Duration::from_secs_f32(1.0 / max_rate)
+            })
+    }
+
     pub fn server_update(&mut self, world: &mut World, comm: &mut TcpCommunicator) {
+        let now = Instant::now();
+        let mut outgoing = Vec::new();
+        let mut bytes_sent = 0;
+
+        world
+            .client_write_validation_mut()
+            .prune(self.client_id, now);
+
+        self.server_update_resources(world, &mut outgoing, &mut bytes_sent);
+
+        let rpcs = self
+            .rpcs
+            .get_or_insert_with(|| world.net_queues().rpcs.subscribe())
+            .recv_all();
+
+        // forgetting a component makes sure it's sent in full below
+        let requests = world.net_queues().incoming_requests.remove(&self.client_id);
+        for (server_entity_id, component_id) in requests.into_iter().flatten() {
+            if let Some(current_components) = self.current_entities.get_mut(&server_entity_id) {
+                current_components.remove(&component_id);
+            }
+        }
+
+        // the client already has what it wrote, so only the server's own changes to it are sent back
+        let writes = world.net_queues().client_writes.remove(&self.client_id);
+        for (server_entity_id, component) in writes.into_iter().flatten() {
+            if let Some(current_components) = self.current_entities.get_mut(&server_entity_id) {
+                current_components.insert(component.component_id(), component);
+            }
+        }
+
         // make sure all relevant entities are present in current_entities
+        let mut entities_left = Vec::<ServerEntityId>::new();
         for (entity_id, (replicate,)) in query!(world, Replicate) {
-            let entity_should_exist_on_client = replicate.owner == Some(self.client_id)
-                || replicate.replicate_to.contains(&self.client_id);
-
-            if entity_should_exist_on_client {
+            if self.is_visible(world, entity_id, replicate) {
                 self.current_entities.entry(entity_id.into()).or_default();
             } else if self.current_entities.remove(&entity_id.into()).is_some() {
-                comm.send(NetEcsCommand::DeleteEntity(entity_id.into()));
+                entities_left.push(entity_id.into());
             }
         }
+        for server_entity_id in entities_left {
+            self.send(
+                &mut outgoing,
+                &mut bytes_sent,
+                NetEcsCommand::DeleteEntity(server_entity_id),
+            );
+        }
 
         // worst part of the borrow checker existing is having to do shit like this
         let mut entities_to_delete = Vec::<ServerEntityId>::new();
         let mut components_to_delete = Vec::<(ServerEntityId, ComponentId)>::new();
+        let mut pending_sets = Vec::<PendingSet>::new();
+        let mut spawns = Vec::<ServerEntityId>::new();
+        let mut held_back_entities = BTreeSet::<ServerEntityId>::new();
 
         // rectify
-        for (&server_entity_id, current_components) in self.current_entities.iter_mut() {
+        for (&server_entity_id, current_components) in self.current_entities.iter() {
             let entity_id = server_entity_id.0;
             if let Some((replicate,)) = query_one!(world, entity_id, Replicate) {
+                // entities the client doesn't have yet are sent whole
+                if current_components.is_empty() {
+                    spawns.push(server_entity_id);
+                    continue;
+                }
+
                 for (&component_id, _) in current_components.iter() {
                     if !world.has_component(entity_id, component_id) {
                         components_to_delete.push((server_entity_id, component_id));
                     }
                 }
 
+                let entity_priority = ReplicationPriority::query_one(world, entity_id)
+                    .map_or(0, |priority| priority.0);
+
                 for (component_id, serializable_component) in
                     world.get_all_serializable_components(entity_id)
                 {
-                    let should_exist = component_id == Replicate::COMPONENT_ID
-                        || replicate.replicated_components.contains(&component_id);
+                    let should_exist = replicate.replicates(component_id);
 
-                    if let Some(current_component) = current_components.get_mut(&component_id) {
+                    let should_send = if let Some(current_component) =
+                        current_components.get(&component_id)
+                    {
                         if !should_exist {
                             components_to_delete.push((server_entity_id, component_id));
+                            false
                         } else {
-                            // we don't want to replicate the client's own changes back to it
-                            let is_self_client_writable = replicate.owner == Some(self.client_id)
-                                && replicate.client_writable.contains(&component_id);
-
                             // always auto-replicate changes made to a Replicate component
                             let should_auto_replicate_changes = current_component.component_id()
                                 == replicate.component_id()
-                                || (!is_self_client_writable
-                                    && replicate.auto_replicate_changes.contains(&component_id));
+                                || replicate.auto_replicate_changes.contains(&component_id);
 
-                            if should_auto_replicate_changes
-                                && current_component.as_ref() != serializable_component
+                            let changed = should_auto_replicate_changes
+                                && current_component.as_ref() != serializable_component;
+                            if changed && self.is_rate_limited(now, server_entity_id, component_id)
                             {
-                                current_components
-                                    .insert(component_id, serializable_component.clone_box());
-                                comm.send(NetEcsCommand::SetComponent(
-                                    server_entity_id,
-                                    serializable_component.clone_box(),
-                                ));
+                                held_back_entities.insert(server_entity_id);
+                                false
+                            } else {
+                                changed
                             }
                         }
-                    } else if should_exist {
-                        current_components.insert(component_id, serializable_component.clone_box());
-                        comm.send(NetEcsCommand::SetComponent(
+                    } else {
+                        should_exist
+                    };
+
+                    if should_send {
+                        let priority = if component_id == Replicate::COMPONENT_ID {
+                            i64::MAX
+                        } else {
+                            let component_priority = self
+                                .replication_rules
+                                .get(&component_id)
+                                .map_or(0, |rule| rule.priority);
+                            let deferred_updates = self
+                                .deferred_updates
+                                .get(&(server_entity_id, component_id))
+                                .copied()
+                                .unwrap_or(0);
+                            entity_priority as i64
+                                + component_priority as i64
+                                + deferred_updates as i64
+                        };
+
+                        pending_sets.push(PendingSet {
                             server_entity_id,
-                            serializable_component.clone_box(),
-                        ));
+                            component_id,
+                            priority,
+                        });
                     }
                 }
             } else {
@@ -158,7 +560,11 @@ impl EcsReplicator {
 
         for server_entity_id in entities_to_delete {
             if self.current_entities.remove(&server_entity_id).is_some() {
-                comm.send(NetEcsCommand::DeleteEntity(server_entity_id));
+                self.send(
+                    &mut outgoing,
+                    &mut bytes_sent,
+                    NetEcsCommand::DeleteEntity(server_entity_id),
+                );
             }
         }
 
@@ -166,15 +572,190 @@ impl EcsReplicator {
             if let Some(current_components) = self.current_entities.get_mut(&server_entity_id)
                 && current_components.remove(&component_id).is_some()
             {
-                comm.send(NetEcsCommand::DeleteComponent(
+                self.send(
+                    &mut outgoing,
+                    &mut bytes_sent,
+                    NetEcsCommand::DeleteComponent(server_entity_id, component_id),
+                );
+            }
+        }
+
+        for server_entity_id in spawns {
+            let Some((replicate,)) = query_one!(world, server_entity_id.0, Replicate) else {
+                continue;
+            };
+
+            let (command, _) = self.spawn_command(world, server_entity_id, replicate, now);
+            self.send(&mut outgoing, &mut bytes_sent, command);
+        }
+
+        // send as many of the changes as fit in the budget, most important first
+        pending_sets.sort_by_key(|pending_set| Reverse(pending_set.priority));
+
+        let mut deferred_updates = BTreeMap::new();
+        let mut sent_any = false;
+        for pending_set in pending_sets {
+            let PendingSet {
+                server_entity_id,
+                component_id,
+                priority,
+            } = pending_set;
+
+            let Some(new) = world
+                .get_component(server_entity_id.0, component_id)
+                .and_then(|component| component.as_serializable())
+            else {
+                continue;
+            };
+            let Some(current_components) = self.current_entities.get(&server_entity_id) else {
+                continue;
+            };
+
+            let command = match current_components.get(&component_id) {
+                Some(old) => Self::set_component_command(
+                    self.delta_compression,
                     server_entity_id,
-                    component_id,
-                ));
+                    old.as_ref(),
+                    new,
+                ),
+                None => NetEcsCommand::SetComponent(server_entity_id, new.clone_box()),
+            };
+
+            let always_sent = priority == i64::MAX;
+            if let Some(byte_budget) = self.byte_budget
+                && !always_sent
+                && sent_any
+                && bytes_sent + command.encoded_len() > byte_budget
+            {
+                let key = (server_entity_id, component_id);
+                let waited = self.deferred_updates.get(&key).copied().unwrap_or(0);
+                deferred_updates.insert(key, waited + 1);
+                held_back_entities.insert(server_entity_id);
+                continue;
+            }
+
+            self.send(&mut outgoing, &mut bytes_sent, command);
+            sent_any |= !always_sent;
+            self.last_sent.insert((server_entity_id, component_id), now);
+            if let Some(current_components) = self.current_entities.get_mut(&server_entity_id) {
+                current_components.insert(component_id, new.clone_box());
+            }
+        }
+        self.deferred_updates = deferred_updates;
+
+        // sent after the components so that an entity is always spawned before its RPCs
+        for rpc in rpcs {
+            if self.current_entities.contains_key(&rpc.server_entity_id) {
+                self.send(&mut outgoing, &mut bytes_sent, rpc.command());
             }
         }
+
+        // inputs are only acknowledged once the state they led to has been sent, and the acknowledgements start over
+        // with every transfer since the new owner counts its inputs from its own start
+        self.acked_inputs.retain(|server_entity_id, _| {
+            self.current_entities.contains_key(server_entity_id)
+                && Ownership::query_one(world, server_entity_id.0).is_none_or(|ownership| {
+                    self.sent_ownership.get(server_entity_id) == Some(&ownership.seq)
+                })
+        });
+        let mut input_acks = Vec::new();
+        for &server_entity_id in self.current_entities.keys() {
+            let entity_id = server_entity_id.0;
+            let is_owner = query_one!(world, entity_id, Replicate)
+                .is_some_and(|(replicate,)| replicate.owner == Some(self.client_id));
+            if !is_owner || held_back_entities.contains(&server_entity_id) {
+                continue;
+            }
+
+            if let Some(&LastProcessedInput(input_tick)) =
+                LastProcessedInput::query_one(world, entity_id)
+                && self.acked_inputs.get(&server_entity_id) != Some(&input_tick)
+            {
+                input_acks.push((server_entity_id, input_tick));
+            }
+        }
+        for (server_entity_id, input_tick) in input_acks {
+            self.acked_inputs.insert(server_entity_id, input_tick);
+            self.send(
+                &mut outgoing,
+                &mut bytes_sent,
+                NetEcsCommand::InputAck(server_entity_id, input_tick),
+            );
+        }
+
+        // like input acknowledgements, transfers wait for the state they happened at so the new owner starts from it
+        self.sent_ownership
+            .retain(|server_entity_id, _| self.current_entities.contains_key(server_entity_id));
+        let mut transfers = Vec::new();
+        for &server_entity_id in self.current_entities.keys() {
+            let entity_id = server_entity_id.0;
+            if held_back_entities.contains(&server_entity_id) {
+                continue;
+            }
+
+            if let Some(ownership) = Ownership::query_one(world, entity_id)
+                && self.sent_ownership.get(&server_entity_id) != Some(&ownership.seq)
+            {
+                let owner =
+                    Replicate::query_one(world, entity_id).and_then(|replicate| replicate.owner);
+                transfers.push((server_entity_id, owner, ownership.seq));
+            }
+        }
+        for (server_entity_id, owner, seq) in transfers {
+            self.sent_ownership.insert(server_entity_id, seq);
+            self.send(
+                &mut outgoing,
+                &mut bytes_sent,
+                NetEcsCommand::TransferOwnership(server_entity_id, owner, seq),
+            );
+        }
+
+        let current_entities = &self.current_entities;
+        self.last_sent
+            .retain(|(server_entity_id, component_id), _| {
+                current_entities
+                    .get(server_entity_id)
+                    .is_some_and(|current_components| current_components.contains_key(component_id))
+            });
+
+        self.flush(comm, outgoing);
     }
 
     pub fn client_update(&mut self, world: &mut World, comm: &mut TcpCommunicator) {
+        for (server_entity_id, component_id) in mem::take(&mut world.net_queues().outgoing_requests)
+        {
+            comm.send(NetEcsCommand::RequestComponent(
+                server_entity_id,
+                component_id,
+            ));
+        }
+
+        let transfers = mem::take(&mut world.net_queues().ownership_transfers);
+        for (server_entity_id, owner, seq) in transfers {
+            if owner != Some(self.client_id) {
+                // stop sending changes straight away, the server won't take them anymore
+                self.current_entities.remove(&server_entity_id);
+                continue;
+            }
+
+            // the world has the server's state as of the transfer, so only changes made from now on are sent
+            let entity_id = world.entity_id_from_server(server_entity_id);
+            let Some((replicate,)) = query_one!(world, entity_id, Replicate) else {
+                continue;
+            };
+            let current_components = world
+                .get_all_serializable_components(entity_id)
+                .filter(|(component_id, _)| {
+                    *component_id != Replicate::COMPONENT_ID
+                        && replicate.client_writable.contains(component_id)
+                })
+                .map(|(component_id, component)| (component_id, component.clone_box()))
+                .collect();
+            self.current_entities
+                .insert(server_entity_id, current_components);
+            comm.send(NetEcsCommand::AcceptOwnership(server_entity_id, seq));
+        }
+
         // make sure all relevant entities are present in current_entities
         for (_, (replicate,)) in query!(world, Replicate) {
             if replicate.owner != Some(self.client_id) {
@@ -242,28 +823,526 @@ impl EcsReplicator {
         }
     }
 
+    /// Sends the current values of some components of an entity to this replicator's client straight away, whether
+    /// or not they have changed or are in [`Replicate::auto_replicate_changes`]. Pass [`Selection::all`] to send the
+    /// whole entity. Forced sends aren't held back by rate limits or the byte budget.
+    ///
+    /// Only components the client would be sent anyway are sent, so nothing happens if the entity isn't visible to
+    /// the client or the component isn't in [`Replicate::replicated_components`]. To send to several clients, call
+    /// this on each of their replicators. An entity the client doesn't have yet is sent whole in a single
+    /// [`NetEcsCommand::SpawnEntity`], whatever `components` is. Returns the number of components sent.
     pub fn replicate(
         &mut self,
-        world: &mut World,
+        world: &World,
         comm: &mut TcpCommunicator,
         entity_id: EntityId,
-        component_id: ComponentId,
-    ) -> bool {
-        if let Some(component) = world.get_component(entity_id, component_id)
-            && let Some(serializeable_component) = component
-                .as_any()
-                .downcast_ref::<Box<dyn SerializableComponent>>()
+        components: &Selection<ComponentId>,
+    ) -> usize {
+        let Some((replicate,)) = query_one!(world, entity_id, Replicate) else {
+            return 0;
+        };
+        if !self.is_visible(world, entity_id, replicate) {
+            return 0;
+        }
+
+        let now = Instant::now();
+        let server_entity_id = ServerEntityId(entity_id);
+
+        // an entity the client doesn't have yet is sent whole, so that it never sees it half built
+        if self
+            .current_entities
+            .get(&server_entity_id)
+            .is_none_or(|current_components| current_components.is_empty())
         {
+            let (command, sent) = self.spawn_command(world, server_entity_id, replicate, now);
+            self.flush(comm, vec![command]);
+            return sent;
+        }
+
+        let mut outgoing = Vec::new();
+        let current_components = self.current_entities.entry(server_entity_id).or_default();
+        for (component_id, component) in world.get_all_serializable_components(entity_id) {
+            if !components.contains(&component_id) || !replicate.replicates(component_id) {
+                continue;
+            }
+
+            outgoing.push(NetEcsCommand::SetComponent(
+                server_entity_id,
+                component.clone_box(),
+            ));
+            current_components.insert(component_id, component.clone_box());
+            self.last_sent.insert((server_entity_id, component_id), now);
+            self.deferred_updates
+                .remove(&(server_entity_id, component_id));
+        }
+
+        let sent = outgoing.len();
+        self.flush(comm, outgoing);
+        sent
+    }
+
+    /// Sends the current values of some components of an entity owned by this client to the server straight away,
+    /// whether or not they have changed. Only components in [`Replicate::client_writable`] are sent. Returns the
+    /// number of components sent.
+    pub fn replicate_to_server(
+        &mut self,
+        world: &World,
+        comm: &mut TcpCommunicator,
+        entity_id: EntityId,
+        components: &Selection<ComponentId>,
+    ) -> usize {
+        let Some((replicate,)) = query_one!(world, entity_id, Replicate) else {
+            return 0;
+        };
+        if replicate.owner != Some(self.client_id) {
+            return 0;
+        }
+
+        let server_entity_id = replicate.server_entity_id;
+        let current_components = self.current_entities.entry(server_entity_id).or_default();
+
+        let mut sent = 0;
+        for (component_id, component) in world.get_all_serializable_components(entity_id) {
+            if component_id == Replicate::COMPONENT_ID
+                || !components.contains(&component_id)
+                || !replicate.client_writable.contains(&component_id)
+            {
+                continue;
+            }
+
             comm.send(NetEcsCommand::SetComponent(
-                entity_id.into(),
-                serializeable_component.clone(),
+                server_entity_id,
+                component.clone_box(),
             ));
-            self.current_entities
-                .entry(entity_id.into())
-                .or_default()
-                .insert(component_id, serializeable_component.clone());
-            return true;
+            current_components.insert(component_id, component.clone_box());
+            sent += 1;
         }
-        false
+
+        sent
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use super::*;
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct ReplicatedHealth(i32);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct ReplicatedArmor(i32);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct ReplicatedClock(u64);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct ReplicatedSecret(u64);
+
+    const CLIENT_ID: ClientId = ClientId(1);
+
+    pub(crate) fn connect() -> (TcpCommunicator, TcpCommunicator) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (
+            TcpCommunicator::new(server, 1 << 20),
+            TcpCommunicator::new(client, 1 << 20),
+        )
+    }
+
+    /// Flushes `from` and returns every command `to` has received from it so far. An empty batch, which replicators
+    /// never send, marks the end of what was sent, so this waits exactly as long as delivery takes.
+    pub(crate) fn deliver(
+        from: &mut TcpCommunicator,
+        to: &mut TcpCommunicator,
+    ) -> Vec<NetEcsCommand> {
+        from.send(NetEcsCommand::Batch(Vec::new()));
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut received = Vec::new();
+        loop {
+            from.update().unwrap();
+            to.update().unwrap();
+            for message in to.recv_all() {
+                match message.downcast_ref::<NetEcsCommand>() {
+                    Some(NetEcsCommand::Batch(commands)) if commands.is_empty() => return received,
+                    Some(command) => received.push(command.clone()),
+                    None => {}
+                }
+            }
+
+            assert!(Instant::now() < deadline, "timed out waiting for commands");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub(crate) fn only(component_id: ComponentId) -> Selection<ComponentId> {
+        let mut selection = Selection::none();
+        selection.allow(component_id);
+        selection
+    }
+
+    /// An entity owned by the client that can write its health, and whose changes are only sent when forced.
+    fn spawn_replicated(world: &mut World) -> EntityId {
+        let entity_id = world.spawn((ReplicatedHealth(10), ReplicatedArmor(5)));
+        world.set_component(
+            entity_id,
+            Replicate {
+                server_entity_id: ServerEntityId(entity_id),
+                owner: Some(CLIENT_ID),
+                replicate_to: Selection::all(),
+                client_writable: only(ReplicatedHealth::COMPONENT_ID),
+                replicated_components: Selection::all(),
+                auto_replicate_changes: Selection::none(),
+            },
+        );
+        entity_id
+    }
+
+    #[test]
+    fn forced_replication_spawns_unknown_entities_whole() {
+        let (mut server_comm, mut client_comm) = connect();
+        let mut server = World::default();
+        let mut client = World::default();
+        let entity_id = spawn_replicated(&mut server);
+        let mut replicator = EcsReplicator::new(CLIENT_ID);
+
+        // asking for a single component of an entity the client doesn't have still sends all of it at once
+        let sent = replicator.replicate(
+            &server,
+            &mut server_comm,
+            entity_id,
+            &only(ReplicatedArmor::COMPONENT_ID),
+        );
+        assert_eq!(sent, 3);
+
+        let received = deliver(&mut server_comm, &mut client_comm);
+        assert_eq!(received.len(), 1);
+        assert!(received[0].is_spawn_entity());
+        for command in received {
+            client.execute_net_command(command).unwrap();
+        }
+
+        let client_entity_id = client.entity_id_from_server(ServerEntityId(entity_id));
+        assert_eq!(
+            ReplicatedHealth::query_one(&client, client_entity_id),
+            Some(&ReplicatedHealth(10))
+        );
+        assert_eq!(
+            ReplicatedArmor::query_one(&client, client_entity_id),
+            Some(&ReplicatedArmor(5))
+        );
+        assert!(Replicate::query_one(&client, client_entity_id).is_some());
+
+        // the next update knows the client has it
+        replicator.server_update(&mut server, &mut server_comm);
+        assert!(deliver(&mut server_comm, &mut client_comm).is_empty());
+    }
+
+    #[test]
+    fn forced_replication_sends_components_to_the_client() {
+        let (mut server_comm, mut client_comm) = connect();
+        let mut server = World::default();
+        let mut client = World::default();
+        let entity_id = spawn_replicated(&mut server);
+        let mut replicator = EcsReplicator::new(CLIENT_ID);
+
+        replicator.server_update(&mut server, &mut server_comm);
+        for command in deliver(&mut server_comm, &mut client_comm) {
+            client.execute_net_command(command).unwrap();
+        }
+        let client_entity_id = client.entity_id_from_server(ServerEntityId(entity_id));
+
+        // changes aren't auto-replicated, so only forcing sends them
+        server.set_component(entity_id, ReplicatedHealth(7));
+        server.set_component(entity_id, ReplicatedArmor(2));
+        replicator.server_update(&mut server, &mut server_comm);
+        assert!(deliver(&mut server_comm, &mut client_comm).is_empty());
+
+        let sent = replicator.replicate(
+            &server,
+            &mut server_comm,
+            entity_id,
+            &only(ReplicatedArmor::COMPONENT_ID),
+        );
+        assert_eq!(sent, 1);
+        for command in deliver(&mut server_comm, &mut client_comm) {
+            client.execute_net_command(command).unwrap();
+        }
+        assert_eq!(
+            ReplicatedHealth::query_one(&client, client_entity_id),
+            Some(&ReplicatedHealth(10))
+        );
+        assert_eq!(
+            ReplicatedArmor::query_one(&client, client_entity_id),
+            Some(&ReplicatedArmor(2))
+        );
+
+        // the whole entity
+        let sent = replicator.replicate(&server, &mut server_comm, entity_id, &Selection::all());
+        assert_eq!(sent, 3);
+        for command in deliver(&mut server_comm, &mut client_comm) {
+            client.execute_net_command(command).unwrap();
+        }
+        assert_eq!(
+            ReplicatedHealth::query_one(&client, client_entity_id),
+            Some(&ReplicatedHealth(7))
+        );
+    }
+
+    #[test]
+    fn forced_replication_sends_writable_components_to_the_server() {
+        let (mut server_comm, mut client_comm) = connect();
+        let mut server = World::default();
+        let mut client = World::default();
+        let entity_id = spawn_replicated(&mut server);
+        let mut server_replicator = EcsReplicator::new(CLIENT_ID);
+        let mut client_replicator = EcsReplicator::new(CLIENT_ID);
+
+        server_replicator.server_update(&mut server, &mut server_comm);
+        for command in deliver(&mut server_comm, &mut client_comm) {
+            client.execute_net_command(command).unwrap();
+        }
+        let client_entity_id = client.entity_id_from_server(ServerEntityId(entity_id));
+
+        client.set_component(client_entity_id, ReplicatedHealth(3));
+        client.set_component(client_entity_id, ReplicatedArmor(0));
+
+        // armor isn't writable by the client, so it's never sent
+        let sent = client_replicator.replicate_to_server(
+            &client,
+            &mut client_comm,
+            client_entity_id,
+            &only(ReplicatedArmor::COMPONENT_ID),
+        );
+        assert_eq!(sent, 0);
+
+        let sent = client_replicator.replicate_to_server(
+            &client,
+            &mut client_comm,
+            client_entity_id,
+            &Selection::all(),
+        );
+        assert_eq!(sent, 1);
+        for command in deliver(&mut client_comm, &mut server_comm) {
+            server
+                .execute_client_net_command(CLIENT_ID, command)
+                .unwrap();
+        }
+        assert_eq!(
+            ReplicatedHealth::query_one(&server, entity_id),
+            Some(&ReplicatedHealth(3))
+        );
+        assert_eq!(
+            ReplicatedArmor::query_one(&server, entity_id),
+            Some(&ReplicatedArmor(5))
+        );
+
+        // a single component
+        client.set_component(client_entity_id, ReplicatedHealth(1));
+        let sent = client_replicator.replicate_to_server(
+            &client,
+            &mut client_comm,
+            client_entity_id,
+            &only(ReplicatedHealth::COMPONENT_ID),
+        );
+        assert_eq!(sent, 1);
+        for command in deliver(&mut client_comm, &mut server_comm) {
+            server
+                .execute_client_net_command(CLIENT_ID, command)
+                .unwrap();
+        }
+        assert_eq!(
+            ReplicatedHealth::query_one(&server, entity_id),
+            Some(&ReplicatedHealth(1))
+        );
+    }
+
+    #[test]
+    fn clients_cannot_batch_or_spawn() {
+        let mut server = World::default();
+        let entity_id = spawn_replicated(&mut server);
+        let server_entity_id = ServerEntityId(entity_id);
+
+        let write = NetEcsCommand::SetComponent(server_entity_id, Box::new(ReplicatedHealth(1)));
+        server
+            .execute_client_net_command(CLIENT_ID, NetEcsCommand::Batch(vec![write]))
+            .unwrap();
+        assert_eq!(
+            ReplicatedHealth::query_one(&server, entity_id),
+            Some(&ReplicatedHealth(10))
+        );
+
+        let mut bundle = SerializableComponentBundle::new();
+        bundle.set_component_boxed(Box::new(ReplicatedHealth(1)));
+        let spawned = ServerEntityId(server.new_entity_id());
+        server
+            .execute_client_net_command(CLIENT_ID, NetEcsCommand::SpawnEntity(spawned, bundle))
+            .unwrap();
+        assert!(!server.has_entity(spawned.0));
+    }
+
+    /// The components set by the commands of one server update, in the order they were sent.
+    fn sent_sets(
+        replicator: &mut EcsReplicator,
+        server: &mut World,
+        server_comm: &mut TcpCommunicator,
+        client_comm: &mut TcpCommunicator,
+    ) -> Vec<(EntityId, ComponentId)> {
+        replicator.server_update(server, server_comm);
+        let mut sets = Vec::new();
+        for command in deliver(server_comm, client_comm) {
+            let commands = match command {
+                NetEcsCommand::Batch(commands) => commands,
+                command => vec![command],
+            };
+            for command in commands {
+                if let NetEcsCommand::SetComponent(server_entity_id, component) = command {
+                    sets.push((server_entity_id.0, component.component_id()));
+                }
+            }
+        }
+        sets
+    }
+
+    #[test]
+    fn byte_budget_defers_changes_by_priority() {
+        let (mut server_comm, mut client_comm) = connect();
+        let mut server = World::default();
+        let mut replicator = EcsReplicator::new(CLIENT_ID);
+        replicator.delta_compression = false;
+
+        let [low, mid, high] = [0, 1, 2].map(|priority| {
+            let entity_id = server.spawn((ReplicatedHealth(10), ReplicationPriority(priority)));
+            server.set_component(
+                entity_id,
+                Replicate {
+                    server_entity_id: ServerEntityId(entity_id),
+                    owner: None,
+                    replicate_to: Selection::all(),
+                    client_writable: Selection::none(),
+                    replicated_components: Selection::all(),
+                    auto_replicate_changes: Selection::all(),
+                },
+            );
+            entity_id
+        });
+        let mut sent_sets = |replicator: &mut EcsReplicator, server: &mut World| {
+            sent_sets(replicator, server, &mut server_comm, &mut client_comm)
+        };
+        let health = ReplicatedHealth::COMPONENT_ID;
+
+        // new entities are spawned regardless of the budget
+        replicator.byte_budget = Some(1);
+        sent_sets(&mut replicator, &mut server);
+        assert_eq!(replicator.deferred_len(), 0);
+
+        // only one change fits in each update, and the rest follow in order of priority
+        for entity_id in [low, mid, high] {
+            server.set_component(entity_id, ReplicatedHealth(5));
+        }
+        assert_eq!(sent_sets(&mut replicator, &mut server), [(high, health)]);
+        assert_eq!(replicator.deferred_len(), 2);
+        assert_eq!(sent_sets(&mut replicator, &mut server), [(mid, health)]);
+        assert_eq!(sent_sets(&mut replicator, &mut server), [(low, health)]);
+        assert_eq!(replicator.deferred_len(), 0);
+        assert!(sent_sets(&mut replicator, &mut server).is_empty());
+
+        // a deferred change gains priority while it waits, so it isn't starved by one that changes every update
+        server.set_component(low, ReplicatedHealth(1));
+        let mut sent = Vec::new();
+        for value in 0..3 {
+            server.set_component(high, ReplicatedHealth(value));
+            sent.extend(sent_sets(&mut replicator, &mut server));
+        }
+        // by the third update both have a priority of 2, and ties go to the entity replicated first
+        assert_eq!(sent, [(high, health), (high, health), (low, health)]);
+        assert_eq!(replicator.deferred_len(), 1);
+        assert_eq!(sent_sets(&mut replicator, &mut server), [(high, health)]);
+
+        // Replicate components are always sent and don't use up the budget
+        for entity_id in [low, mid, high] {
+            server.set_component(entity_id, ReplicatedHealth(0));
+        }
+        Replicate::query_one_mut(&mut server, low)
+            .unwrap()
+            .client_writable = only(health);
+        assert_eq!(
+            sent_sets(&mut replicator, &mut server),
+            [(low, Replicate::COMPONENT_ID), (high, health)]
+        );
+        assert_eq!(replicator.deferred_len(), 2);
+        assert_eq!(sent_sets(&mut replicator, &mut server), [(mid, health)]);
+        assert_eq!(sent_sets(&mut replicator, &mut server), [(low, health)]);
+    }
+
+    #[test]
+    fn resources_are_replicated_to_the_client() {
+        let (mut server_comm, mut client_comm) = connect();
+        let mut server = World::default();
+        let mut client = World::default();
+        let mut replicator = EcsReplicator::new(CLIENT_ID);
+        replicator.replicated_resources = only(ReplicatedClock::COMPONENT_ID);
+        let mut update =
+            |replicator: &mut EcsReplicator, server: &mut World, client: &mut World| {
+                replicator.server_update(server, &mut server_comm);
+                let received = deliver(&mut server_comm, &mut client_comm);
+                for command in received.clone() {
+                    client.execute_net_command(command).unwrap();
+                }
+                received
+            };
+
+        // only the selected resources are sent
+        server.insert_resource(ReplicatedClock(0));
+        server.insert_resource(ReplicatedSecret(7));
+        let received = update(&mut replicator, &mut server, &mut client);
+        assert!(matches!(&received[..], [NetEcsCommand::SetResource(_)]));
+        assert_eq!(
+            client.get_resource::<ReplicatedClock>(),
+            Some(&ReplicatedClock(0))
+        );
+        assert!(client.get_resource::<ReplicatedSecret>().is_none());
+
+        // and only again once they change
+        assert!(update(&mut replicator, &mut server, &mut client).is_empty());
+        server.insert_resource(ReplicatedClock(60));
+        server.insert_resource(ReplicatedSecret(8));
+        let received = update(&mut replicator, &mut server, &mut client);
+        assert!(matches!(&received[..], [NetEcsCommand::SetResource(_)]));
+        assert_eq!(
+            client.get_resource::<ReplicatedClock>(),
+            Some(&ReplicatedClock(60))
+        );
+
+        // removing a resource on the server removes it on the client
+        server.remove_resource::<ReplicatedClock>();
+        let received = update(&mut replicator, &mut server, &mut client);
+        assert!(matches!(
+            &received[..],
+            [NetEcsCommand::DeleteResource(component_id)] if *component_id == ReplicatedClock::COMPONENT_ID
+        ));
+        assert!(client.get_resource::<ReplicatedClock>().is_none());
+
+        // and so does no longer replicating it
+        server.insert_resource(ReplicatedClock(1));
+        update(&mut replicator, &mut server, &mut client);
+        assert_eq!(
+            client.get_resource::<ReplicatedClock>(),
+            Some(&ReplicatedClock(1))
+        );
+        replicator.replicated_resources = Selection::none();
+        let received = update(&mut replicator, &mut server, &mut client);
+        assert!(matches!(&received[..], [NetEcsCommand::DeleteResource(_)]));
+        assert!(client.get_resource::<ReplicatedClock>().is_none());
     }
 }
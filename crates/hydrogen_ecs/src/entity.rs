@@ -1,8 +1,11 @@
+use std::fmt;
+
 use derive_more::*;
 use serde::{Deserialize, Serialize};
 
+use crate::naming;
+
 #[derive(
-    Debug,
     Clone,
     Copy,
     Serialize,
@@ -25,3 +28,9 @@ impl From<usize> for EntityId {
         Self(value as u32)
     }
 }
+
+impl fmt::Debug for EntityId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        naming::fmt_entity_id(f, *self, false)
+    }
+}
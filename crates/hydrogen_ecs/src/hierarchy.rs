@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    component::{Component, SerializableComponent},
+    ecs_net::ServerEntityId,
+    entity::EntityId,
+    reflect::Reflect,
+    world::World,
+};
+
+mod hydrogen {
+    pub use crate as ecs;
+}
+
+/// The entity that this entity is attached to. Setting or deleting this component keeps the parent's [`Children`] up
+/// to date. Setting it to the entity itself or one of its descendants is refused, since that would create a cycle.
+///
+/// When replicated, the server's entity id is mapped to the client's.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, SerializableComponent, Reflect)]
+pub struct Parent(pub EntityId);
+
+/// Every entity attached to this entity, in the order they were attached. This is maintained by the [`World`] and
+/// should not be set manually; use [`World::set_parent`] or set a [`Parent`] component on the child instead. Never
+/// replicated, as each world builds its own from its [`Parent`] components.
+#[derive(
+    Debug, Clone, Default, Serialize, Deserialize, PartialEq, SerializableComponent, Reflect,
+)]
+pub struct Children(pub Vec<EntityId>);
+
+impl World {
+    pub fn parent(&self, entity_id: EntityId) -> Option<EntityId> {
+        Parent::query_one(self, entity_id).map(|parent| parent.0)
+    }
+
+    pub fn children(&self, entity_id: EntityId) -> &[EntityId] {
+        Children::query_one(self, entity_id)
+            .map(|children| children.0.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Attaches `child` to `parent`, detaching it from its previous parent. Returns `false` and does nothing if this
+    /// would create a cycle.
+    pub fn set_parent(&mut self, child: EntityId, parent: EntityId) -> bool {
+        if self.would_create_cycle(child, parent) {
+            return false;
+        }
+
+        self.set_component(child, Parent(parent));
+        true
+    }
+
+    /// Detaches `child` from its parent, returning the old parent.
+    pub fn remove_parent(&mut self, child: EntityId) -> Option<EntityId> {
+        let parent = self.parent(child)?;
+        self.delete_component(child, Parent::COMPONENT_ID);
+        Some(parent)
+    }
+
+    /// Iterates over the parent of `entity_id`, then its grandparent, and so on up to the root.
+    pub fn ancestors(&self, entity_id: EntityId) -> impl Iterator<Item = EntityId> {
+        let mut current = entity_id;
+        std::iter::from_fn(move || {
+            current = self.parent(current)?;
+            Some(current)
+        })
+    }
+
+    /// Every entity below `entity_id` in the hierarchy, in depth-first order. Does not include `entity_id` itself.
+    pub fn descendants(&self, entity_id: EntityId) -> Vec<EntityId> {
+        let mut descendants = Vec::new();
+        let mut stack: Vec<EntityId> = self.children(entity_id).iter().rev().copied().collect();
+
+        while let Some(current) = stack.pop() {
+            descendants.push(current);
+            stack.extend(self.children(current).iter().rev());
+        }
+
+        descendants
+    }
+
+    pub fn is_descendant_of(&self, entity_id: EntityId, ancestor: EntityId) -> bool {
+        self.ancestors(entity_id).any(|id| id == ancestor)
+    }
+
+    /// Returns the topmost ancestor of `entity_id`, or `entity_id` itself if it has no parent.
+    pub fn root(&self, entity_id: EntityId) -> EntityId {
+        self.ancestors(entity_id).last().unwrap_or(entity_id)
+    }
+
+    /// Deletes an entity along with all of its descendants.
+    pub fn delete_entity_recursive(&mut self, entity_id: EntityId) -> bool {
+        for descendant in self.descendants(entity_id).into_iter().rev() {
+            self.delete_entity(descendant);
+        }
+        self.delete_entity(entity_id)
+    }
+
+    /// Whether attaching `child` to `parent` would make `child` its own ancestor.
+    pub(crate) fn would_create_cycle(&self, child: EntityId, parent: EntityId) -> bool {
+        child == parent || self.is_descendant_of(parent, child)
+    }
+
+    /// Replaces the server's entity id in a replicated [`Parent`] with this world's.
+    pub(crate) fn map_server_entity_ids(
+        &mut self,
+        component: Box<dyn SerializableComponent>,
+    ) -> Box<dyn SerializableComponent> {
+        match component.downcast_ref::<Parent>() {
+            Some(&Parent(parent)) => {
+                Box::new(Parent(self.entity_id_from_server(ServerEntityId(parent))))
+            }
+            None => component,
+        }
+    }
+
+    /// Adds `child` to the [`Children`] of `parent`, removing it from the [`Children`] of its previous parent.
+    pub(crate) fn attach_child(&mut self, parent: EntityId, child: EntityId) {
+        if let Some(old_parent) = self.parent(child) {
+            if old_parent == parent {
+                return;
+            }
+            self.detach_child(old_parent, child);
+        }
+
+        // replaced as a whole rather than changed in place so that the hooks of Children run
+        let mut children = self.children(parent).to_vec();
+        if !children.contains(&child) {
+            children.push(child);
+            self.set_component_boxed(parent, Box::new(Children(children)));
+        }
+    }
+
+    fn detach_child(&mut self, parent: EntityId, child: EntityId) {
+        let children = self.children(parent);
+        if !children.contains(&child) {
+            return;
+        }
+
+        let children: Vec<EntityId> = children.iter().copied().filter(|&id| id != child).collect();
+        if children.is_empty() {
+            self.delete_component(parent, Children::COMPONENT_ID);
+        } else {
+            self.set_component_boxed(parent, Box::new(Children(children)));
+        }
+    }
+
+    /// Removes an entity from its parent and orphans its children, in preparation for it being deleted.
+    pub(crate) fn detach_from_hierarchy(&mut self, entity_id: EntityId) {
+        if let Some(parent) = self.parent(entity_id) {
+            self.detach_child(parent, entity_id);
+        }
+
+        for child in self.children(entity_id).to_vec() {
+            self.delete_component(child, Parent::COMPONENT_ID);
+        }
+    }
+
+    pub(crate) fn on_hierarchy_component_deleted(
+        &mut self,
+        entity_id: EntityId,
+        component: &dyn Component,
+    ) {
+        if let Some(&Parent(parent)) = component.downcast_ref::<Parent>() {
+            self.detach_child(parent, entity_id);
+        } else if let Some(Children(children)) = component.downcast_ref::<Children>() {
+            for &child in children {
+                self.delete_component(child, Parent::COMPONENT_ID);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs_net::NetEcsCommand;
+
+    #[test]
+    fn cyclic_parents_are_refused() {
+        let mut world = World::default();
+        let grandparent = world.new_entity_id();
+        let parent = world.new_entity_id();
+        let child = world.new_entity_id();
+        world.set_component(parent, Parent(grandparent));
+        world.set_component(child, Parent(parent));
+
+        assert!(world.set_component(grandparent, Parent(child)).is_none());
+        assert!(
+            world
+                .set_component_boxed(grandparent, Box::new(Parent(grandparent)))
+                .is_none()
+        );
+        assert!(!world.set_parent(grandparent, child));
+
+        assert_eq!(world.parent(grandparent), None);
+        assert_eq!(world.children(child), &[] as &[EntityId]);
+        assert_eq!(world.root(child), grandparent);
+    }
+
+    #[test]
+    fn replicated_parents_are_mapped_to_client_entities() {
+        let mut server = World::default();
+        let server_parent = server.new_entity_id();
+        let server_child = server.new_entity_id();
+
+        let mut client = World::default();
+        client
+            .execute_net_command(NetEcsCommand::SetComponent(
+                ServerEntityId(server_child),
+                Box::new(Parent(server_parent)),
+            ))
+            .unwrap();
+
+        let client_parent = client.entity_id_from_server(ServerEntityId(server_parent));
+        let client_child = client.entity_id_from_server(ServerEntityId(server_child));
+        assert_eq!(client.parent(client_child), Some(client_parent));
+        assert_eq!(client.children(client_parent), &[client_child]);
+    }
+}
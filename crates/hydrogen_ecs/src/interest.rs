@@ -0,0 +1,305 @@
+use std::{fmt, sync::Arc};
+
+use cgmath::{MetricSpace, Vector3};
+
+use crate::{entity::EntityId, transform::GlobalTransform, world::World};
+
+pub type RelevanceFn = Arc<dyn Fn(&World, EntityId) -> bool + Send + Sync>;
+
+/// Which of the entities a client is allowed to see are actually sent to it, set with
+/// [`EcsReplicator::interest`](crate::ecs_net::EcsReplicator::interest). Entities owned by the client are always
+/// relevant.
+///
+/// Entities are checked on every server update. One that stops being relevant is deleted on the client, and one that
+/// becomes relevant is sent in full.
+#[derive(Clone, Default)]
+pub enum Interest {
+    /// Every entity the client is allowed to see.
+    #[default]
+    All,
+    /// Entities within `radius` of `center`. Entities without a [`GlobalTransform`] are always relevant.
+    Area { center: Vector3<f32>, radius: f32 },
+    /// Entities within `radius` of another entity, usually the client's avatar. Only entities without a
+    /// [`GlobalTransform`] are relevant while `entity_id` doesn't have one.
+    AroundEntity { entity_id: EntityId, radius: f32 },
+    /// Entities that the function returns `true` for.
+    Custom(RelevanceFn),
+}
+
+impl fmt::Debug for Interest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => write!(f, "All"),
+            Self::Area { center, radius } => f
+                .debug_struct("Area")
+                .field("center", center)
+                .field("radius", radius)
+                .finish(),
+            Self::AroundEntity { entity_id, radius } => f
+                .debug_struct("AroundEntity")
+                .field("entity_id", entity_id)
+                .field("radius", radius)
+                .finish(),
+            Self::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+impl PartialEq for Interest {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::All, Self::All) => true,
+            (
+                Self::Area { center, radius },
+                Self::Area {
+                    center: other_center,
+                    radius: other_radius,
+                },
+            ) => center == other_center && radius == other_radius,
+            (
+                Self::AroundEntity { entity_id, radius },
+                Self::AroundEntity {
+                    entity_id: other_entity_id,
+                    radius: other_radius,
+                },
+            ) => entity_id == other_entity_id && radius == other_radius,
+            (Self::Custom(relevance), Self::Custom(other_relevance)) => {
+                Arc::ptr_eq(relevance, other_relevance)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn position(world: &World, entity_id: EntityId) -> Option<Vector3<f32>> {
+    GlobalTransform::query_one(world, entity_id).map(GlobalTransform::translation)
+}
+
+impl Interest {
+    pub fn custom(relevance: impl Fn(&World, EntityId) -> bool + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(relevance))
+    }
+
+    pub fn is_relevant(&self, world: &World, entity_id: EntityId) -> bool {
+        let within = |center: Vector3<f32>, radius: f32| {
+            position(world, entity_id)
+                .is_none_or(|position| position.distance2(center) <= radius * radius)
+        };
+
+        match self {
+            Self::All => true,
+            Self::Area { center, radius } => within(*center, *radius),
+            Self::AroundEntity {
+                entity_id: center_entity_id,
+                radius,
+            } => match position(world, *center_entity_id) {
+                Some(center) => within(center, *radius),
+                None => position(world, entity_id).is_none(),
+            },
+            Self::Custom(relevance) => relevance(world, entity_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Matrix4, vec3};
+    use hydrogen_data_structures::selection::Selection;
+    use hydrogen_net::{comm::TcpCommunicator, server_client::ClientId};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{
+        component::SerializableComponent,
+        ecs_net::{
+            EcsReplicator, NetEcsCommand, Replicate, ServerEntityId,
+            tests::{connect, deliver},
+        },
+    };
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct InterestHealth(i32);
+
+    const CLIENT_ID: ClientId = ClientId(1);
+
+    fn spawn(
+        world: &mut World,
+        position: Option<Vector3<f32>>,
+        owner: Option<ClientId>,
+    ) -> EntityId {
+        let entity_id = world.spawn((InterestHealth(10),));
+        world.set_component(
+            entity_id,
+            Replicate {
+                server_entity_id: ServerEntityId(entity_id),
+                owner,
+                replicate_to: Selection::all(),
+                client_writable: Selection::none(),
+                replicated_components: Selection::all(),
+                auto_replicate_changes: Selection::all(),
+            },
+        );
+        if let Some(position) = position {
+            move_to(world, entity_id, position);
+        }
+        entity_id
+    }
+
+    fn move_to(world: &mut World, entity_id: EntityId, position: Vector3<f32>) {
+        world.set_component(
+            entity_id,
+            GlobalTransform(Matrix4::from_translation(position)),
+        );
+    }
+
+    /// A server and a client world kept in sync by a replicator with the given interest.
+    struct Session {
+        server: World,
+        client: World,
+        replicator: EcsReplicator,
+        server_comm: TcpCommunicator,
+        client_comm: TcpCommunicator,
+    }
+
+    impl Session {
+        fn new(server: World, interest: Interest) -> Self {
+            let (server_comm, client_comm) = connect();
+            let mut replicator = EcsReplicator::new(CLIENT_ID);
+            replicator.interest = interest;
+            Self {
+                server,
+                client: World::default(),
+                replicator,
+                server_comm,
+                client_comm,
+            }
+        }
+
+        /// Runs a server update, applies what was sent to the client world and returns which entities were spawned
+        /// and deleted.
+        fn update(&mut self) -> (Vec<EntityId>, Vec<EntityId>) {
+            self.replicator
+                .server_update(&mut self.server, &mut self.server_comm);
+
+            let mut spawned = Vec::new();
+            let mut deleted = Vec::new();
+            for command in deliver(&mut self.server_comm, &mut self.client_comm) {
+                let commands = match command {
+                    NetEcsCommand::Batch(commands) => commands,
+                    command => vec![command],
+                };
+                for command in commands {
+                    match &command {
+                        NetEcsCommand::SpawnEntity(server_entity_id, _) => {
+                            spawned.push(server_entity_id.0)
+                        }
+                        NetEcsCommand::DeleteEntity(server_entity_id) => {
+                            deleted.push(server_entity_id.0)
+                        }
+                        _ => {}
+                    }
+                    self.client.execute_net_command(command).unwrap();
+                }
+            }
+            (spawned, deleted)
+        }
+
+        fn client_health(&self, entity_id: EntityId) -> Option<i32> {
+            let client_entity_id = self
+                .client
+                .try_entity_id_from_server(ServerEntityId(entity_id))?;
+            InterestHealth::query_one(&self.client, client_entity_id).map(|health| health.0)
+        }
+    }
+
+    #[test]
+    fn area_interest() {
+        let mut server = World::default();
+        let near = spawn(&mut server, Some(vec3(1.0, 0.0, 0.0)), None);
+        let far = spawn(&mut server, Some(vec3(20.0, 0.0, 0.0)), None);
+        let nowhere = spawn(&mut server, None, None);
+        let owned = spawn(&mut server, Some(vec3(50.0, 0.0, 0.0)), Some(CLIENT_ID));
+
+        let interest = Interest::Area {
+            center: vec3(0.0, 0.0, 0.0),
+            radius: 10.0,
+        };
+        assert!(interest.is_relevant(&server, near));
+        assert!(!interest.is_relevant(&server, far));
+        assert!(interest.is_relevant(&server, nowhere));
+
+        // entities the client owns are sent wherever they are
+        let mut session = Session::new(server, interest);
+        assert_eq!(session.update(), (vec![near, nowhere, owned], vec![]));
+        assert_eq!(session.update(), (vec![], vec![]));
+
+        // leaving the area deletes the entity on the client
+        move_to(&mut session.server, near, vec3(0.0, 11.0, 0.0));
+        assert_eq!(session.update(), (vec![], vec![near]));
+        assert_eq!(session.client_health(near), None);
+
+        // and coming back sends it whole again, including what changed while it was away
+        session.server.set_component(near, InterestHealth(3));
+        assert_eq!(session.update(), (vec![], vec![]));
+        move_to(&mut session.server, near, vec3(0.0, 9.0, 0.0));
+        move_to(&mut session.server, far, vec3(0.0, 0.0, -10.0));
+        assert_eq!(session.update(), (vec![near, far], vec![]));
+        assert_eq!(session.client_health(near), Some(3));
+        assert_eq!(session.client_health(far), Some(10));
+    }
+
+    #[test]
+    fn around_entity_interest() {
+        let mut server = World::default();
+        let avatar = spawn(&mut server, None, Some(CLIENT_ID));
+        let other = spawn(&mut server, Some(vec3(0.0, 0.0, 30.0)), None);
+        let nowhere = spawn(&mut server, None, None);
+
+        // only entities without a position are relevant while the avatar doesn't have one
+        let mut session = Session::new(
+            server,
+            Interest::AroundEntity {
+                entity_id: avatar,
+                radius: 5.0,
+            },
+        );
+        assert_eq!(session.update(), (vec![avatar, nowhere], vec![]));
+
+        move_to(&mut session.server, avatar, vec3(0.0, 0.0, 26.0));
+        assert_eq!(session.update(), (vec![other], vec![]));
+
+        // the area moves with the avatar
+        move_to(&mut session.server, avatar, vec3(0.0, 0.0, 0.0));
+        assert_eq!(session.update(), (vec![], vec![other]));
+        move_to(&mut session.server, other, vec3(3.0, 0.0, 0.0));
+        assert_eq!(session.update(), (vec![other], vec![]));
+        assert_eq!(session.client_health(other), Some(10));
+    }
+
+    #[test]
+    fn custom_interest() {
+        let mut server = World::default();
+        let healthy = spawn(&mut server, None, None);
+        let hurt = spawn(&mut server, None, None);
+        server.set_component(hurt, InterestHealth(1));
+
+        let interest = Interest::custom(|world, entity_id| {
+            InterestHealth::query_one(world, entity_id).is_some_and(|health| health.0 >= 5)
+        });
+        assert_eq!(interest, interest.clone());
+        assert_ne!(interest, Interest::custom(|_, _| true));
+
+        let mut session = Session::new(server, interest);
+        assert_eq!(session.update(), (vec![healthy], vec![]));
+
+        session.server.set_component(healthy, InterestHealth(4));
+        session.server.set_component(hurt, InterestHealth(8));
+        assert_eq!(session.update(), (vec![hurt], vec![healthy]));
+        assert_eq!(session.client_health(healthy), None);
+        assert_eq!(session.client_health(hurt), Some(8));
+    }
+}
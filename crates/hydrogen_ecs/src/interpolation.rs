@@ -0,0 +1,430 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use cgmath::{InnerSpace, Quaternion, Vector2, Vector3, Vector4};
+
+use crate::{
+    component::{ComponentId, ComponentType, SerializableComponent},
+    ecs_net::{NetEcsCommand, ServerEntityId},
+    migration::MigrationError,
+    transform::Transform,
+    world::World,
+};
+
+/// Blends between two values of a component, for [`Interpolation`].
+pub trait Interpolate {
+    /// `t` is 0 at `self` and 1 at `other`, and goes past 1 when extrapolating.
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for f64 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t as f64
+    }
+}
+
+impl Interpolate for Vector2<f32> {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Vector3<f32> {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Vector4<f32> {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Quaternion<f32> {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        // take the short way around
+        let other = if self.dot(*other) < 0.0 {
+            -*other
+        } else {
+            *other
+        };
+        (self + (other - self) * t).normalize()
+    }
+}
+
+impl Interpolate for Transform {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.interpolate(&other.translation, t),
+            rotation: self.rotation.interpolate(&other.rotation, t),
+            scale: self.scale.interpolate(&other.scale, t),
+        }
+    }
+}
+
+type InterpolateFn = fn(
+    &dyn SerializableComponent,
+    &dyn SerializableComponent,
+    f32,
+) -> Option<Box<dyn SerializableComponent>>;
+
+fn interpolate_component<T: SerializableComponent + Interpolate>(
+    from: &dyn SerializableComponent,
+    to: &dyn SerializableComponent,
+    t: f32,
+) -> Option<Box<dyn SerializableComponent>> {
+    let from = from.downcast_ref::<T>()?;
+    let to = to.downcast_ref::<T>()?;
+    Some(Box::new(from.interpolate(to, t)))
+}
+
+type Samples = VecDeque<(Instant, Box<dyn SerializableComponent>)>;
+
+/// Smooths out the movement of entities on a client by showing the server's values of some of their components a
+/// little in the past, blending between the two values on either side of that time.
+///
+/// Values are timestamped when they arrive. If the next value is late, the last two are extrapolated from for at most
+/// [`max_extrapolation`](Interpolation::max_extrapolation), after which the entity stops until a new value arrives.
+///
+/// Meant for entities controlled by the server or other clients. Entities owned by this client are better served by
+/// [`Prediction`](crate::prediction::Prediction).
+#[derive(Debug)]
+pub struct Interpolation {
+    /// How far in the past entities are shown. Should be a bit more than the time between server updates, so that
+    /// there's usually a newer value to blend towards.
+    pub delay: Duration,
+    pub max_extrapolation: Duration,
+    interpolators: BTreeMap<ComponentId, InterpolateFn>,
+    samples: BTreeMap<(ServerEntityId, ComponentId), Samples>,
+}
+
+impl Interpolation {
+    pub fn new(delay: Duration, max_extrapolation: Duration) -> Self {
+        Self {
+            delay,
+            max_extrapolation,
+            interpolators: Default::default(),
+            samples: Default::default(),
+        }
+    }
+
+    /// Starts interpolating every replicated `T`.
+    pub fn interpolate<T: ComponentType + SerializableComponent + Interpolate>(
+        &mut self,
+    ) -> &mut Self {
+        self.interpolators
+            .insert(T::COMPONENT_ID, interpolate_component::<T>);
+        self
+    }
+
+    pub fn is_interpolated(&self, component_id: ComponentId) -> bool {
+        self.interpolators.contains_key(&component_id)
+    }
+
+    /// Use instead of [`World::execute_net_command`] for commands from the server, so that new values of interpolated
+    /// components are buffered. A component's first value is applied straight away. To predict the entities this
+    /// client owns as well, use a [`ClientNet`](crate::client_net::ClientNet).
+    pub fn execute_net_command(
+        &mut self,
+        world: &mut World,
+        command: NetEcsCommand,
+        now: Instant,
+    ) -> Result<(), MigrationError> {
+        if let NetEcsCommand::Batch(commands) = command {
+            for command in world.migrate_net_commands(commands)? {
+                self.execute_net_command(world, command, now)?;
+            }
+            return Ok(());
+        }
+
+        if let Some(command) = self.handle_net_command(world, command, now)? {
+            world.execute_net_command(command)?;
+        }
+        Ok(())
+    }
+
+    /// Buffers new values of interpolated components, and forgets the buffered values of components and entities that
+    /// are removed before handing the command back to be applied.
+    pub(crate) fn handle_net_command(
+        &mut self,
+        world: &mut World,
+        command: NetEcsCommand,
+        now: Instant,
+    ) -> Result<Option<NetEcsCommand>, MigrationError> {
+        match command {
+            NetEcsCommand::SetComponent(server_entity_id, component)
+                if self.is_interpolated(component.component_id()) =>
+            {
+                let component = world.migrations().migrate(component)?;
+                self.push(world, server_entity_id, component, now);
+            }
+            NetEcsCommand::SetComponentDelta(server_entity_id, delta)
+                if self.is_interpolated(delta.component_id) =>
+            {
+                // deltas are made against the newest value the server sent, which may not be shown yet
+                let component = self
+                    .samples
+                    .get(&(server_entity_id, delta.component_id))
+                    .and_then(|samples| samples.back())
+                    .and_then(|(_, newest)| delta.apply(newest.as_ref()).ok());
+
+                match component {
+                    Some(component) => {
+                        let component = world.migrations().migrate(component)?;
+                        self.push(world, server_entity_id, component, now);
+                    }
+                    None => world
+                        .net_queues()
+                        .outgoing_requests
+                        .push((server_entity_id, delta.component_id)),
+                }
+            }
+            NetEcsCommand::DeleteComponent(server_entity_id, component_id) => {
+                self.samples.remove(&(server_entity_id, component_id));
+                return Ok(Some(command));
+            }
+            NetEcsCommand::SpawnEntity(server_entity_id, _) => {
+                self.forget(server_entity_id);
+                world.execute_net_command(command)?;
+
+                // the initial values are the first samples
+                let entity_id = world.entity_id_from_server(server_entity_id);
+                for (component_id, component) in world.get_all_serializable_components(entity_id) {
+                    if self.is_interpolated(component_id) {
+                        self.samples
+                            .entry((server_entity_id, component_id))
+                            .or_default()
+                            .push_back((now, component.clone_box()));
+                    }
+                }
+            }
+            NetEcsCommand::DeleteEntity(server_entity_id) => {
+                self.forget(server_entity_id);
+                return Ok(Some(command));
+            }
+            command => return Ok(Some(command)),
+        }
+
+        Ok(None)
+    }
+
+    /// The entities with buffered values of any of their components.
+    pub fn interpolated_entities(&self) -> BTreeSet<ServerEntityId> {
+        self.samples
+            .keys()
+            .map(|&(server_entity_id, _)| server_entity_id)
+            .collect()
+    }
+
+    /// Drops every buffered value of an entity, so that it's no longer interpolated until new values arrive.
+    pub fn forget(&mut self, server_entity_id: ServerEntityId) {
+        self.samples
+            .retain(|&(sampled_entity_id, _), _| sampled_entity_id != server_entity_id);
+    }
+
+    fn push(
+        &mut self,
+        world: &mut World,
+        server_entity_id: ServerEntityId,
+        component: Box<dyn SerializableComponent>,
+        now: Instant,
+    ) {
+        let entity_id = world.entity_id_from_server(server_entity_id);
+        if !world.has_component(entity_id, component.component_id()) {
+            world.set_component_boxed(entity_id, component.clone_box());
+        }
+
+        self.samples
+            .entry((server_entity_id, component.component_id()))
+            .or_default()
+            .push_back((now, component));
+    }
+
+    /// Sets every interpolated component to its value at `now` minus the [`delay`](Interpolation::delay).
+    pub fn update(&mut self, world: &mut World, now: Instant) {
+        let Some(render_time) = now.checked_sub(self.delay) else {
+            return;
+        };
+
+        for (&(server_entity_id, component_id), samples) in self.samples.iter_mut() {
+            // only the newest value at or before the render time is needed from now on
+            while samples.len() > 2 && samples[1].0 <= render_time {
+                samples.pop_front();
+            }
+
+            let interpolate = self.interpolators[&component_id];
+            let value = match samples.iter().position(|&(time, _)| time > render_time) {
+                // everything is still in the future
+                Some(0) => samples[0].1.clone_box(),
+                Some(next) => {
+                    let (from_time, from) = &samples[next - 1];
+                    let (to_time, to) = &samples[next];
+                    let t = (render_time - *from_time).as_secs_f32()
+                        / (*to_time - *from_time).as_secs_f32();
+                    interpolate(from.as_ref(), to.as_ref(), t).unwrap_or_else(|| to.clone_box())
+                }
+                None => match samples.len() {
+                    0 => continue,
+                    1 => samples[0].1.clone_box(),
+                    len => {
+                        let (from_time, from) = &samples[len - 2];
+                        let (to_time, to) = &samples[len - 1];
+                        // values that arrived together say nothing about how fast the component changes
+                        if to_time == from_time {
+                            to.clone_box()
+                        } else {
+                            let extrapolate_to = render_time.min(*to_time + self.max_extrapolation);
+                            let t = (extrapolate_to - *from_time).as_secs_f32()
+                                / (*to_time - *from_time).as_secs_f32();
+                            interpolate(from.as_ref(), to.as_ref(), t)
+                                .unwrap_or_else(|| to.clone_box())
+                        }
+                    }
+                },
+            };
+
+            let entity_id = world.entity_id_from_server(server_entity_id);
+            world.set_component_boxed(entity_id, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::entity::EntityId;
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct InterpolatedPosition(f32);
+
+    impl Interpolate for InterpolatedPosition {
+        fn interpolate(&self, other: &Self, t: f32) -> Self {
+            Self(self.0.interpolate(&other.0, t))
+        }
+    }
+
+    const SERVER_ENTITY_ID: ServerEntityId = ServerEntityId(EntityId(0));
+
+    fn set_position(position: f32) -> NetEcsCommand {
+        NetEcsCommand::SetComponent(SERVER_ENTITY_ID, Box::new(InterpolatedPosition(position)))
+    }
+
+    fn position(world: &mut World) -> f32 {
+        let entity_id = world.entity_id_from_server(SERVER_ENTITY_ID);
+        InterpolatedPosition::query_one(world, entity_id).unwrap().0
+    }
+
+    /// A tiny deterministic generator, so that the simulated network is the same on every run.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> f32 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 40) as f32 / (1u64 << 24) as f32
+        }
+    }
+
+    #[test]
+    fn values_arriving_together_are_held() {
+        let start = Instant::now();
+        let mut interpolation =
+            Interpolation::new(Duration::from_millis(100), Duration::from_millis(200));
+        interpolation.interpolate::<InterpolatedPosition>();
+        let mut world = World::default();
+
+        interpolation
+            .execute_net_command(&mut world, set_position(1.0), start)
+            .unwrap();
+        interpolation
+            .execute_net_command(&mut world, set_position(2.0), start)
+            .unwrap();
+        interpolation.update(&mut world, start + Duration::from_millis(500));
+
+        assert_eq!(position(&mut world), 2.0);
+    }
+
+    /// The server moves an entity at a constant speed and sends its position every 50ms, which arrives after 30-50ms
+    /// and is lost a fifth of the time. The client shows it 100ms in the past at 60 frames a second.
+    #[test]
+    fn movement_stays_smooth_over_a_jittery_lossy_network() {
+        const SPEED: f32 = 10.0;
+        const SEND_INTERVAL: Duration = Duration::from_millis(50);
+        const LATENCY: Duration = Duration::from_millis(30);
+        const MAX_JITTER: Duration = Duration::from_millis(20);
+        const FRAME: Duration = Duration::from_micros(16_667);
+        /// How far off the shown position may be, which is how far the entity moves in the jitter plus a little.
+        const TOLERANCE: f32 = SPEED * 0.05;
+
+        let start = Instant::now();
+        let mut random = Lcg(7);
+        let mut arrivals = Vec::new();
+        let mut sends = 0;
+        for tick in 0..200u32 {
+            let sent_at = SEND_INTERVAL * tick;
+            // always deliver the first value so the entity exists
+            if tick > 0 && random.next() < 0.2 {
+                continue;
+            }
+
+            let arrives_at = sent_at + LATENCY + MAX_JITTER.mul_f32(random.next());
+            arrivals.push((arrives_at, SPEED * sent_at.as_secs_f32()));
+            sends += 1;
+        }
+        assert!(sends < 190, "some values should be lost");
+
+        let delay = Duration::from_millis(100);
+        let mut interpolation = Interpolation::new(delay, Duration::from_millis(150));
+        interpolation.interpolate::<InterpolatedPosition>();
+        let mut world = World::default();
+
+        let mut arrivals = arrivals.into_iter().peekable();
+        let mut last_position = f32::NEG_INFINITY;
+        let mut now = Duration::ZERO;
+        while now < SEND_INTERVAL * 200 {
+            while let Some(&(arrives_at, position)) = arrivals.peek()
+                && arrives_at <= now
+            {
+                interpolation
+                    .execute_net_command(&mut world, set_position(position), start + arrives_at)
+                    .unwrap();
+                arrivals.next();
+            }
+
+            interpolation.update(&mut world, start + now);
+            if now > delay + LATENCY + MAX_JITTER {
+                let shown = position(&mut world);
+                let expected = SPEED * (now - delay - LATENCY).as_secs_f32();
+                assert!(
+                    (shown - expected).abs() <= TOLERANCE,
+                    "showed {shown} at {now:?}, expected about {expected}"
+                );
+                // an extrapolation that overshot is pulled back, but never by more than it was off by
+                assert!(
+                    shown >= last_position - TOLERANCE,
+                    "moved backwards from {last_position} to {shown} at {now:?}"
+                );
+                last_position = shown;
+            }
+
+            now += FRAME;
+        }
+    }
+}
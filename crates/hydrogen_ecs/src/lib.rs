@@ -1,7 +1,28 @@
 #![allow(clippy::needless_arbitrary_self_type, clippy::borrowed_box)]
 
+pub mod change_detection;
 pub mod change_tracker;
+pub mod client_net;
+pub mod commands;
 pub mod component;
+pub mod delta;
+pub mod diff;
 pub mod ecs_net;
 pub mod entity;
+pub mod hierarchy;
+pub mod interest;
+pub mod interpolation;
+pub mod lifecycle;
+pub mod migration;
+pub mod naming;
+pub mod ownership;
+pub mod prediction;
+pub mod prefab;
+pub mod reflect;
+pub mod registry;
+pub mod rpc;
+pub mod snapshot;
+pub mod spatial;
+pub mod transform;
+pub mod validation;
 pub mod world;
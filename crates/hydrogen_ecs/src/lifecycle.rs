@@ -0,0 +1,358 @@
+use std::{collections::BTreeMap, fmt, marker::PhantomData, sync::Arc};
+
+use hydrogen_core::events::{EventReceiver, EventSender};
+
+use crate::{
+    component::{Component, ComponentId, ComponentType},
+    entity::EntityId,
+    world::World,
+};
+
+pub type InsertHook = Arc<dyn Fn(&mut World, EntityId) + Send + Sync>;
+pub type RemoveHook = Arc<dyn Fn(&mut World, EntityId, &dyn Component) + Send + Sync>;
+pub type DespawnHook = Arc<dyn Fn(&mut World, EntityId) + Send + Sync>;
+
+#[derive(Default, Clone)]
+struct ComponentHooks {
+    on_insert: Vec<InsertHook>,
+    on_replace: Vec<RemoveHook>,
+    on_remove: Vec<RemoveHook>,
+}
+
+/// Callbacks that run when components are added to, replaced on, or removed from entities, and when entities are
+/// deleted. Hooks run right after the change has been made to the world.
+#[derive(Default)]
+pub struct WorldHooks {
+    component_hooks: BTreeMap<ComponentId, ComponentHooks>,
+    on_despawn: Vec<DespawnHook>,
+    removed_component_senders: BTreeMap<ComponentId, EventSender<EntityId>>,
+}
+
+impl fmt::Debug for WorldHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorldHooks")
+            .field(
+                "component_hooks",
+                &format!("({} component types)", self.component_hooks.len()),
+            )
+            .field("on_despawn", &format!("({} hooks)", self.on_despawn.len()))
+            .field("removed_component_senders", &self.removed_component_senders)
+            .finish()
+    }
+}
+
+impl WorldHooks {
+    /// Runs when a `T` component is added to an entity that didn't already have one.
+    pub fn on_insert<T: ComponentType>(
+        &mut self,
+        hook: impl Fn(&mut World, EntityId) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.component_hooks
+            .entry(T::COMPONENT_ID)
+            .or_default()
+            .on_insert
+            .push(Arc::new(hook));
+        self
+    }
+
+    /// Runs when a `T` component is set on an entity that already had one, with the old value.
+    pub fn on_replace<T: ComponentType>(
+        &mut self,
+        hook: impl Fn(&mut World, EntityId, &T) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.component_hooks
+            .entry(T::COMPONENT_ID)
+            .or_default()
+            .on_replace
+            .push(Arc::new(move |world, entity_id, old_component| {
+                if let Some(old_component) = old_component.downcast_ref::<T>() {
+                    hook(world, entity_id, old_component);
+                }
+            }));
+        self
+    }
+
+    /// Runs when a `T` component is removed from an entity, including when the whole entity is deleted, with the
+    /// removed value.
+    pub fn on_remove<T: ComponentType>(
+        &mut self,
+        hook: impl Fn(&mut World, EntityId, &T) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.component_hooks
+            .entry(T::COMPONENT_ID)
+            .or_default()
+            .on_remove
+            .push(Arc::new(move |world, entity_id, removed_component| {
+                if let Some(removed_component) = removed_component.downcast_ref::<T>() {
+                    hook(world, entity_id, removed_component);
+                }
+            }));
+        self
+    }
+
+    /// Runs after an entity has been deleted or taken with [`World::take_bundle`], once the
+    /// [`on_remove`](Self::on_remove) hooks of its components have run.
+    pub fn on_despawn(
+        &mut self,
+        hook: impl Fn(&mut World, EntityId) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_despawn.push(Arc::new(hook));
+        self
+    }
+
+    pub fn clear_component_hooks(&mut self, component_id: ComponentId) {
+        self.component_hooks.remove(&component_id);
+    }
+
+    fn removed_components<T>(&mut self, component_id: ComponentId) -> RemovedComponents<T> {
+        RemovedComponents {
+            receiver: self
+                .removed_component_senders
+                .entry(component_id)
+                .or_default()
+                .subscribe(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Receives the IDs of entities that had a `T` component removed, either directly or by being deleted. Only removals
+/// that happen after this was created are received.
+pub struct RemovedComponents<T> {
+    receiver: EventReceiver<EntityId>,
+    _phantom: PhantomData<T>,
+}
+
+impl<T> fmt::Debug for RemovedComponents<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemovedComponents")
+            .field("receiver", &self.receiver)
+            .finish()
+    }
+}
+
+impl<T> RemovedComponents<T> {
+    pub fn is_empty(&self) -> bool {
+        self.receiver.peek().is_none()
+    }
+
+    /// Returns every entity that had the component removed since the last read.
+    pub fn read(&self) -> Vec<EntityId> {
+        self.receiver
+            .recv_all()
+            .into_iter()
+            .map(|entity_id| *entity_id)
+            .collect()
+    }
+}
+
+impl World {
+    pub fn removed_components<T: ComponentType>(&mut self) -> RemovedComponents<T> {
+        self.hooks_mut().removed_components(T::COMPONENT_ID)
+    }
+
+    pub(crate) fn run_insert_hooks(&mut self, entity_id: EntityId, component_id: ComponentId) {
+        let Some(component_hooks) = self.hooks().component_hooks.get(&component_id) else {
+            return;
+        };
+
+        for hook in component_hooks.on_insert.clone() {
+            hook(self, entity_id);
+        }
+    }
+
+    pub(crate) fn run_replace_hooks(&mut self, entity_id: EntityId, old_component: &dyn Component) {
+        let Some(component_hooks) = self
+            .hooks()
+            .component_hooks
+            .get(&old_component.component_id())
+        else {
+            return;
+        };
+
+        for hook in component_hooks.on_replace.clone() {
+            hook(self, entity_id, old_component);
+        }
+    }
+
+    pub(crate) fn run_remove_hooks(
+        &mut self,
+        entity_id: EntityId,
+        removed_component: &dyn Component,
+    ) {
+        let component_id = removed_component.component_id();
+
+        if let Some(sender) = self.hooks().removed_component_senders.get(&component_id)
+            && sender.receiver_count() > 0
+        {
+            sender.send(entity_id);
+        }
+
+        let Some(component_hooks) = self.hooks().component_hooks.get(&component_id) else {
+            return;
+        };
+
+        for hook in component_hooks.on_remove.clone() {
+            hook(self, entity_id, removed_component);
+        }
+    }
+
+    pub(crate) fn run_despawn_hooks(&mut self, entity_id: EntityId) {
+        for hook in self.hooks().on_despawn.clone() {
+            hook(self, entity_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::hierarchy::{Children, Parent};
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Component)]
+    struct HookedHealth(u32);
+
+    #[derive(Debug, Clone, PartialEq, Component)]
+    struct HookedArmor(u32);
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    fn record(log: &Log, entry: String) {
+        log.lock().unwrap().push(entry);
+    }
+
+    fn take(log: &Log) -> Vec<String> {
+        std::mem::take(&mut *log.lock().unwrap())
+    }
+
+    fn hooked_world() -> (World, Log) {
+        let mut world = World::default();
+        let log = Log::default();
+        let (insert, replace, remove, despawn) =
+            (log.clone(), log.clone(), log.clone(), log.clone());
+        world
+            .hooks_mut()
+            .on_insert::<HookedHealth>(move |world, entity_id| {
+                let health = HookedHealth::query_one(world, entity_id).unwrap().0;
+                record(&insert, format!("insert {health}"));
+            })
+            .on_replace::<HookedHealth>(move |world, entity_id, old| {
+                let new = HookedHealth::query_one(world, entity_id).unwrap().0;
+                record(&replace, format!("replace {} with {new}", old.0));
+            })
+            .on_remove::<HookedHealth>(move |world, entity_id, removed| {
+                assert!(HookedHealth::query_one(world, entity_id).is_none());
+                record(&remove, format!("remove {}", removed.0));
+            })
+            .on_despawn(move |world, entity_id| {
+                assert!(!world.has_entity(entity_id));
+                record(&despawn, "despawn".to_owned());
+            });
+        (world, log)
+    }
+
+    #[test]
+    fn hooks_run_in_order_after_each_change() {
+        let (mut world, log) = hooked_world();
+
+        let entity_id = world.spawn((HookedHealth(10), HookedArmor(1)));
+        world.set_component(entity_id, HookedHealth(5));
+        world.delete_component(entity_id, HookedHealth::COMPONENT_ID);
+        world.set_component(entity_id, HookedHealth(3));
+        assert_eq!(
+            take(&log),
+            ["insert 10", "replace 10 with 5", "remove 5", "insert 3"]
+        );
+
+        // the components are removed before the entity is despawned
+        world.delete_entity(entity_id);
+        assert_eq!(take(&log), ["remove 3", "despawn"]);
+        assert!(!world.delete_entity(entity_id));
+        assert!(take(&log).is_empty());
+    }
+
+    #[test]
+    fn taking_a_bundle_despawns_the_entity() {
+        let (mut world, log) = hooked_world();
+        let entity_id = world.spawn((HookedHealth(10), HookedArmor(1)));
+        take(&log);
+
+        let bundle = world.take_bundle(entity_id);
+        assert_eq!(bundle.len(), 2);
+        assert_eq!(take(&log), ["remove 10", "despawn"]);
+
+        assert!(world.take_bundle(entity_id).is_empty());
+        assert!(take(&log).is_empty());
+    }
+
+    #[test]
+    fn removed_components_are_received_once() {
+        let mut world = World::default();
+        let before = world.spawn((HookedHealth(1),));
+        world.delete_entity(before);
+
+        let removed = world.removed_components::<HookedHealth>();
+        assert!(removed.is_empty());
+
+        let [deleted, taken, stripped, untouched] =
+            [0, 1, 2, 3].map(|health| world.spawn((HookedHealth(health), HookedArmor(0))));
+        world.delete_entity(deleted);
+        world.take_bundle(taken);
+        world.delete_component(stripped, HookedHealth::COMPONENT_ID);
+        world.delete_component(untouched, HookedArmor::COMPONENT_ID);
+
+        assert!(!removed.is_empty());
+        assert_eq!(removed.read(), [deleted, taken, stripped]);
+        assert!(removed.is_empty());
+        assert!(removed.read().is_empty());
+    }
+
+    #[test]
+    fn children_changes_run_hooks() {
+        let mut world = World::default();
+        let log = Log::default();
+        let (insert, replace, remove) = (log.clone(), log.clone(), log.clone());
+        world
+            .hooks_mut()
+            .on_insert::<Children>(move |world, entity_id| {
+                record(&insert, format!("insert {:?}", world.children(entity_id)));
+            })
+            .on_replace::<Children>(move |world, entity_id, old| {
+                record(
+                    &replace,
+                    format!("replace {:?} with {:?}", old.0, world.children(entity_id)),
+                );
+            })
+            .on_remove::<Children>(move |_, _, removed| {
+                record(&remove, format!("remove {:?}", removed.0));
+            });
+
+        let parent = world.spawn((HookedHealth(0),));
+        let first = world.spawn((Parent(parent),));
+        let second = world.spawn((Parent(parent),));
+        assert_eq!(
+            take(&log),
+            [
+                format!("insert {:?}", [first]),
+                format!("replace {:?} with {:?}", [first], [first, second]),
+            ]
+        );
+
+        world.remove_parent(first);
+        world.delete_entity(second);
+        assert_eq!(
+            take(&log),
+            [
+                format!("replace {:?} with {:?}", [first, second], [second]),
+                format!("remove {:?}", [second]),
+            ]
+        );
+        assert_eq!(world.children(parent), &[] as &[EntityId]);
+    }
+}
@@ -0,0 +1,202 @@
+use std::{any::Any, collections::BTreeMap, fmt, sync::Arc};
+
+use thiserror::Error;
+
+use crate::{
+    component::{ComponentId, ComponentType, SerializableComponent},
+    registry::ComponentRegistry,
+};
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error(
+        "no migration registered for {display_name} ({component_id:?}) from version {from_version}, current version is {current_version}"
+    )]
+    MissingMigration {
+        component_id: ComponentId,
+        display_name: &'static str,
+        from_version: u32,
+        current_version: u32,
+    },
+    #[error("migration for {component_id:?} version {version} expected {expected}, got {actual}")]
+    TypeMismatch {
+        component_id: ComponentId,
+        version: u32,
+        expected: &'static str,
+        actual: &'static str,
+    },
+}
+
+type MigrationFn = Arc<
+    dyn Fn(Box<dyn SerializableComponent>) -> Result<Box<dyn SerializableComponent>, MigrationError>
+        + Send
+        + Sync,
+>;
+
+/// Upgrades serialized components from older versions to their current version.
+///
+/// Each version of a component that can still show up in save files or network messages needs its own type with the
+/// same `#[component(id = "...")]` and its old `version`, so that its payload can be deserialized. Migrations are then
+/// registered from each old version to the next one.
+#[derive(Default)]
+pub struct ComponentMigrations {
+    migrations: BTreeMap<(ComponentId, u32), MigrationFn>,
+    current_versions: BTreeMap<ComponentId, u32>,
+}
+
+impl fmt::Debug for ComponentMigrations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComponentMigrations")
+            .field("migrations", &self.migrations.keys().collect::<Vec<_>>())
+            .field("current_versions", &self.current_versions)
+            .finish()
+    }
+}
+
+impl ComponentMigrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a migration from `Old` to `New`, which must share a component ID and have increasing versions.
+    pub fn register<Old, New>(
+        &mut self,
+        migrate: impl Fn(Old) -> New + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        Old: ComponentType + SerializableComponent,
+        New: ComponentType + SerializableComponent,
+    {
+        assert!(
+            Old::COMPONENT_ID == New::COMPONENT_ID,
+            "can't migrate between different components ({} and {})",
+            Old::DISPLAY_NAME,
+            New::DISPLAY_NAME
+        );
+        assert!(
+            Old::COMPONENT_VERSION < New::COMPONENT_VERSION,
+            "migrations must go from an older version to a newer one ({} v{} to {} v{})",
+            Old::DISPLAY_NAME,
+            Old::COMPONENT_VERSION,
+            New::DISPLAY_NAME,
+            New::COMPONENT_VERSION
+        );
+
+        self.migrations.insert(
+            (Old::COMPONENT_ID, Old::COMPONENT_VERSION),
+            Arc::new(move |component| {
+                let actual = component.display_name();
+                match Box::<dyn Any + 'static>::downcast::<Old>(component) {
+                    Ok(old) => Ok(Box::new(migrate(*old))),
+                    Err(_) => Err(MigrationError::TypeMismatch {
+                        component_id: Old::COMPONENT_ID,
+                        version: Old::COMPONENT_VERSION,
+                        expected: Old::DISPLAY_NAME,
+                        actual,
+                    }),
+                }
+            }),
+        );
+
+        let current_version = self.current_versions.entry(New::COMPONENT_ID).or_default();
+        *current_version = (*current_version).max(New::COMPONENT_VERSION);
+
+        self
+    }
+
+    /// The newest version of the component, out of the types linked into the program and the migrations registered.
+    pub fn current_version(&self, component_id: ComponentId) -> Option<u32> {
+        let registered = ComponentRegistry::global()
+            .get(component_id)
+            .map(|registration| registration.version);
+        let migrated = self.current_versions.get(&component_id).copied();
+        registered.max(migrated)
+    }
+
+    /// Runs every migration needed to bring `component` up to its current version. Fails if it's an older version
+    /// that no chain of registered migrations leads up from, since it shares its [`ComponentId`] with the current type
+    /// but can't be stored in its place.
+    pub fn migrate(
+        &self,
+        mut component: Box<dyn SerializableComponent>,
+    ) -> Result<Box<dyn SerializableComponent>, MigrationError> {
+        let component_id = component.component_id();
+        let Some(current_version) = self.current_version(component_id) else {
+            return Ok(component);
+        };
+
+        while component.component_version() < current_version {
+            let Some(migration) = self
+                .migrations
+                .get(&(component_id, component.component_version()))
+            else {
+                return Err(MigrationError::MissingMigration {
+                    component_id,
+                    display_name: component.display_name(),
+                    from_version: component.component_version(),
+                    current_version,
+                });
+            };
+
+            component = migration(component)?;
+        }
+
+        Ok(component)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{component::ComponentSet, entity::EntityId};
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    #[component(id = "migration_score")]
+    struct OldScore(i32);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    #[component(id = "migration_score", version = 1)]
+    struct Score(i64);
+
+    #[test]
+    fn old_versions_without_migrations_are_refused() {
+        let migrations = ComponentMigrations::new();
+        assert_eq!(migrations.current_version(Score::COMPONENT_ID), Some(1));
+
+        let error = migrations.migrate(Box::new(OldScore(3))).unwrap_err();
+        assert!(matches!(
+            error,
+            MigrationError::MissingMigration {
+                from_version: 0,
+                current_version: 1,
+                ..
+            }
+        ));
+
+        let migrated = migrations.migrate(Box::new(Score(3))).unwrap();
+        assert_eq!(migrated.downcast_ref::<Score>(), Some(&Score(3)));
+    }
+
+    #[test]
+    fn old_versions_are_migrated() {
+        let mut migrations = ComponentMigrations::new();
+        migrations.register(|OldScore(score): OldScore| Score(score as i64 * 10));
+
+        let migrated = migrations.migrate(Box::new(OldScore(3))).unwrap();
+        assert_eq!(migrated.downcast_ref::<Score>(), Some(&Score(30)));
+    }
+
+    #[test]
+    #[should_panic(expected = "type mismatch")]
+    fn component_sets_refuse_other_types_with_the_same_id() {
+        let mut component_set = ComponentSet::new(Score::COMPONENT_ID);
+        component_set.set(EntityId(0), Box::new(Score(1)), Default::default());
+        component_set.set(EntityId(1), Box::new(OldScore(1)), Default::default());
+    }
+}
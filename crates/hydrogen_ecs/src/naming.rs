@@ -0,0 +1,372 @@
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet},
+    fmt, ptr,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    change_detection::{QueryFilter, Tick},
+    component::{Component, ComponentId, SerializableComponent},
+    ecs_net::ServerEntityId,
+    entity::EntityId,
+    reflect::Reflect,
+    world::World,
+};
+
+mod hydrogen {
+    pub use crate as ecs;
+}
+
+/// A human-readable name for an entity, shown next to its ID in `Debug` output inside [`World::named`]. Names don't
+/// have to be unique.
+///
+/// The world keeps an index of names for [`World::entities_named`], which also picks up names changed in place through
+/// `query_mut` or `get_component_mut` by looking at their change ticks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SerializableComponent, Reflect)]
+pub struct Name(pub String);
+
+impl Name {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+}
+
+/// Free-form labels for an entity, indexed by the world for [`World::entities_tagged`] in the same way as [`Name`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, SerializableComponent)]
+pub struct Tags(pub BTreeSet<String>);
+
+impl<S: Into<String>> FromIterator<S> for Tags {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Which entities have each [`Name`] and tag.
+///
+/// Components set or deleted through the world are indexed straight away. Ones changed in place are found from their
+/// change ticks the next time the index is used.
+#[derive(Debug, Default)]
+pub(crate) struct NameIndex {
+    indexed: Mutex<IndexedNames>,
+}
+
+#[derive(Debug, Default)]
+struct IndexedNames {
+    names: BTreeMap<String, BTreeSet<EntityId>>,
+    tags: BTreeMap<String, BTreeSet<EntityId>>,
+    entity_names: BTreeMap<EntityId, String>,
+    entity_tags: BTreeMap<EntityId, BTreeSet<String>>,
+    /// The world's change tick when components changed in place were last looked for.
+    synced_at: Tick,
+}
+
+fn index_insert(map: &mut BTreeMap<String, BTreeSet<EntityId>>, key: &str, entity_id: EntityId) {
+    map.entry(key.to_string()).or_default().insert(entity_id);
+}
+
+fn index_remove(map: &mut BTreeMap<String, BTreeSet<EntityId>>, key: &str, entity_id: EntityId) {
+    if let Some(entity_ids) = map.get_mut(key) {
+        entity_ids.remove(&entity_id);
+        if entity_ids.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+impl IndexedNames {
+    fn set_name(&mut self, entity_id: EntityId, name: Option<&str>) {
+        if self.entity_names.get(&entity_id).map(String::as_str) == name {
+            return;
+        }
+
+        if let Some(old_name) = self.entity_names.remove(&entity_id) {
+            index_remove(&mut self.names, &old_name, entity_id);
+        }
+        if let Some(name) = name {
+            index_insert(&mut self.names, name, entity_id);
+            self.entity_names.insert(entity_id, name.to_string());
+        }
+    }
+
+    fn set_tags(&mut self, entity_id: EntityId, tags: Option<&BTreeSet<String>>) {
+        if self.entity_tags.get(&entity_id) == tags {
+            return;
+        }
+
+        for old_tag in self.entity_tags.remove(&entity_id).into_iter().flatten() {
+            index_remove(&mut self.tags, &old_tag, entity_id);
+        }
+        if let Some(tags) = tags {
+            for tag in tags {
+                index_insert(&mut self.tags, tag, entity_id);
+            }
+            self.entity_tags.insert(entity_id, tags.clone());
+        }
+    }
+
+    /// Re-indexes the names and tags that were changed in place since the last sync.
+    fn sync(&mut self, world: &World) {
+        // anything stamped with the tick of the last sync may have changed after it ran
+        let since = Tick(self.synced_at.0.saturating_sub(1));
+        let changed_since_sync =
+            |component_id| world.component_set_last_changed(component_id) > Some(since);
+
+        if changed_since_sync(Name::COMPONENT_ID) {
+            let filter = QueryFilter::Changed(Name::COMPONENT_ID);
+            for (entity_id, [name]) in
+                world.query_filtered([Name::COMPONENT_ID], [], [filter], since)
+            {
+                let name = name.downcast_ref::<Name>().unwrap();
+                self.set_name(entity_id, Some(&name.0));
+            }
+        }
+
+        if changed_since_sync(Tags::COMPONENT_ID) {
+            let filter = QueryFilter::Changed(Tags::COMPONENT_ID);
+            for (entity_id, [tags]) in
+                world.query_filtered([Tags::COMPONENT_ID], [], [filter], since)
+            {
+                let tags = tags.downcast_ref::<Tags>().unwrap();
+                self.set_tags(entity_id, Some(&tags.0));
+            }
+        }
+
+        self.synced_at = world.change_tick();
+    }
+}
+
+impl NameIndex {
+    pub(crate) fn indexes(component_id: ComponentId) -> bool {
+        component_id == Name::COMPONENT_ID || component_id == Tags::COMPONENT_ID
+    }
+
+    pub(crate) fn on_component_set(&mut self, entity_id: EntityId, component: &dyn Component) {
+        let indexed = self.indexed.get_mut().unwrap();
+        if let Some(Name(name)) = component.downcast_ref::<Name>() {
+            indexed.set_name(entity_id, Some(name));
+        } else if let Some(Tags(tags)) = component.downcast_ref::<Tags>() {
+            indexed.set_tags(entity_id, Some(tags));
+        }
+    }
+
+    pub(crate) fn on_component_deleted(&mut self, entity_id: EntityId, component: &dyn Component) {
+        let indexed = self.indexed.get_mut().unwrap();
+        if component.downcast_ref::<Name>().is_some() {
+            indexed.set_name(entity_id, None);
+        } else if component.downcast_ref::<Tags>().is_some() {
+            indexed.set_tags(entity_id, None);
+        }
+    }
+
+    fn entities_named(&self, world: &World, name: &str) -> Vec<EntityId> {
+        let mut indexed = self.indexed.lock().unwrap();
+        indexed.sync(world);
+        indexed
+            .names
+            .get(name)
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    fn entities_tagged(&self, world: &World, tag: &str) -> Vec<EntityId> {
+        let mut indexed = self.indexed.lock().unwrap();
+        indexed.sync(world);
+        indexed
+            .tags
+            .get(tag)
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect()
+    }
+}
+
+impl World {
+    pub fn name(&self, entity_id: EntityId) -> Option<&str> {
+        Name::query_one(self, entity_id).map(|name| name.0.as_str())
+    }
+
+    pub fn set_name(&mut self, entity_id: EntityId, name: impl Into<String>) {
+        self.set_component(entity_id, Name(name.into()));
+    }
+
+    /// Every entity with the given [`Name`].
+    pub fn entities_named(&self, name: &str) -> impl Iterator<Item = EntityId> {
+        self.name_index().entities_named(self, name).into_iter()
+    }
+
+    /// The first entity with the given [`Name`], for when names are known to be unique.
+    pub fn find_by_name(&self, name: &str) -> Option<EntityId> {
+        self.entities_named(name).next()
+    }
+
+    pub fn has_tag(&self, entity_id: EntityId, tag: &str) -> bool {
+        Tags::query_one(self, entity_id).is_some_and(|tags| tags.0.contains(tag))
+    }
+
+    /// Returns `false` if the entity already had the tag.
+    pub fn add_tag(&mut self, entity_id: EntityId, tag: impl Into<String>) -> bool {
+        let mut tags = Tags::query_one(self, entity_id)
+            .cloned()
+            .unwrap_or_default();
+        let added = tags.0.insert(tag.into());
+        if added {
+            self.set_component(entity_id, tags);
+        }
+        added
+    }
+
+    /// Returns `false` if the entity didn't have the tag. Removes the [`Tags`] component once it's empty.
+    pub fn remove_tag(&mut self, entity_id: EntityId, tag: &str) -> bool {
+        let Some(mut tags) = Tags::query_one(self, entity_id).cloned() else {
+            return false;
+        };
+
+        let removed = tags.0.remove(tag);
+        if tags.0.is_empty() {
+            self.delete_component(entity_id, Tags::COMPONENT_ID);
+        } else if removed {
+            self.set_component(entity_id, tags);
+        }
+        removed
+    }
+
+    /// Every entity with the given tag in its [`Tags`].
+    pub fn entities_tagged(&self, tag: &str) -> impl Iterator<Item = EntityId> {
+        self.name_index().entities_tagged(self, tag).into_iter()
+    }
+
+    /// Wraps a value so that every [`EntityId`] and [`ServerEntityId`] in its [`Debug`](fmt::Debug) output is shown
+    /// with its [`Name`], e.g. `EntityId(3 "player")`. This works for anything that prints IDs with their own `Debug`
+    /// impls, such as [`NetEcsCommand`](crate::ecs_net::NetEcsCommand) and
+    /// [`ComponentTrackerEvent`](crate::change_tracker::ComponentTrackerEvent).
+    ///
+    /// IDs inside a [`ServerEntityId`] are looked up through the world's server entity mapping if it has one, so that
+    /// both server and client worlds show the right names.
+    pub fn named<'a, T: fmt::Debug + ?Sized>(&'a self, value: &'a T) -> Named<'a, T> {
+        Named { world: self, value }
+    }
+
+    fn name_for_debug(&self, entity_id: EntityId, is_server_entity_id: bool) -> Option<&str> {
+        let entity_id = if is_server_entity_id && self.has_server_entity_ids() {
+            self.try_entity_id_from_server(ServerEntityId(entity_id))?
+        } else {
+            entity_id
+        };
+
+        self.name(entity_id)
+    }
+}
+
+thread_local! {
+    /// The world whose names entity IDs are printed with, set while a [`Named`] is being formatted.
+    static NAMING_WORLD: Cell<*const World> = const { Cell::new(ptr::null()) };
+}
+
+/// Formats an entity ID as `EntityId(3)`, or `EntityId(3 "player")` inside [`World::named`]. Used by the `Debug` impls
+/// of [`EntityId`] and [`ServerEntityId`].
+pub(crate) fn fmt_entity_id(
+    f: &mut fmt::Formatter<'_>,
+    entity_id: EntityId,
+    is_server_entity_id: bool,
+) -> fmt::Result {
+    // SAFETY: the pointer is only set while `Named::fmt` is borrowing the world
+    let world = unsafe { NAMING_WORLD.get().as_ref() };
+    match world.and_then(|world| world.name_for_debug(entity_id, is_server_entity_id)) {
+        Some(name) => write!(f, "EntityId({} {name:?})", entity_id.0),
+        None => write!(f, "EntityId({})", entity_id.0),
+    }
+}
+
+/// See [`World::named`].
+pub struct Named<'a, T: ?Sized> {
+    world: &'a World,
+    value: &'a T,
+}
+
+impl<T: fmt::Debug + ?Sized> fmt::Debug for Named<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        /// Puts back the previous world when dropped, even if formatting panics.
+        struct Restore(*const World);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                NAMING_WORLD.set(self.0);
+            }
+        }
+
+        let _restore = Restore(NAMING_WORLD.replace(self.world));
+        self.value.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs_net::NetEcsCommand;
+
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct ClientEntityId(u32);
+
+    #[test]
+    fn debug_output_names_entity_ids() {
+        let mut world = World::default();
+        let player = world.spawn((Name::new("player"),));
+        let unnamed = world.new_entity_id();
+
+        let command = NetEcsCommand::DeleteComponent(ServerEntityId(player), Name::COMPONENT_ID);
+        assert!(format!("{:?}", world.named(&command)).contains(&format!(
+            "ServerEntityId(EntityId({} \"player\"))",
+            player.0
+        )));
+
+        // only real entity IDs are named, not other types that happen to end in `EntityId`
+        let lookalike = ClientEntityId(player.0);
+        assert_eq!(
+            format!("{:?}", world.named(&lookalike)),
+            format!("{lookalike:?}")
+        );
+        assert_eq!(
+            format!("{:?}", world.named(&[player, unnamed])),
+            format!(
+                "[EntityId({} \"player\"), EntityId({})]",
+                player.0, unnamed.0
+            )
+        );
+        assert_eq!(format!("{player:?}"), format!("EntityId({})", player.0));
+    }
+
+    #[test]
+    fn names_changed_in_place_are_indexed() {
+        let mut world = World::default();
+        let entity_id = world.spawn((Name::new("player"), Tags::from_iter(["friendly"])));
+        assert_eq!(world.find_by_name("player"), Some(entity_id));
+
+        Name::query_one_mut(&mut world, entity_id).unwrap().0 = "hero".to_string();
+        Tags::query_one_mut(&mut world, entity_id).unwrap().0 =
+            BTreeSet::from(["hostile".to_string()]);
+
+        assert_eq!(world.find_by_name("player"), None);
+        assert_eq!(world.find_by_name("hero"), Some(entity_id));
+        assert_eq!(world.entities_tagged("friendly").count(), 0);
+        assert_eq!(
+            world.entities_tagged("hostile").collect::<Vec<_>>(),
+            [entity_id]
+        );
+
+        // later ticks are picked up as well
+        world.increment_change_tick();
+        Name::query_one_mut(&mut world, entity_id).unwrap().0 = "villain".to_string();
+        assert_eq!(world.find_by_name("hero"), None);
+        assert_eq!(world.find_by_name("villain"), Some(entity_id));
+
+        world.delete_entity(entity_id);
+        assert_eq!(world.find_by_name("villain"), None);
+    }
+}
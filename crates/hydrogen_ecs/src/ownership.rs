@@ -0,0 +1,316 @@
+use derive_more::*;
+use hydrogen_net::server_client::ClientId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    component::Component,
+    ecs_net::{Replicate, ServerEntityId},
+    entity::EntityId,
+    prediction::LastProcessedInput,
+    world::World,
+};
+
+mod hydrogen {
+    pub use crate as ecs;
+}
+
+/// Counts the ownership transfers of an entity, so that messages from before a transfer can be told apart from ones
+/// after it.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    From,
+    Into,
+    Add,
+    AddAssign,
+    Sub,
+    SubAssign,
+)]
+pub struct OwnershipSeq(pub u32);
+
+/// The state of the last ownership transfer of an entity, added by [`World::transfer_ownership`]. Only used by the
+/// server.
+///
+/// Entities that have never been transferred don't have one, and take writes from whoever [`Replicate::owner`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub struct Ownership {
+    pub seq: OwnershipSeq,
+    /// Whether the new owner has accepted the entity. Until it has, its writes and inputs are rejected, since they
+    /// may have been sent before it knew about the transfer.
+    pub acknowledged: bool,
+}
+
+impl World {
+    /// Hands authority over a replicated entity to a client, or back to the server with `None`.
+    ///
+    /// Inputs the old owner sent that haven't been processed yet are dropped, and the entity's [`LastProcessedInput`]
+    /// is removed so that the new owner's inputs are applied from the first one.
+    ///
+    /// Every client that has the entity is told with a [`NetEcsCommand::TransferOwnership`] after the entity's current
+    /// state. The old owner stops sending changes straight away, and the new owner only starts once it has the
+    /// server's state as of the transfer, which it acknowledges with a [`NetEcsCommand::AcceptOwnership`].
+    ///
+    /// Returns `None` if the entity isn't replicated.
+    ///
+    /// [`NetEcsCommand::TransferOwnership`]: crate::ecs_net::NetEcsCommand::TransferOwnership
+    /// [`NetEcsCommand::AcceptOwnership`]: crate::ecs_net::NetEcsCommand::AcceptOwnership
+    pub fn transfer_ownership(
+        &mut self,
+        entity_id: EntityId,
+        owner: Option<ClientId>,
+    ) -> Option<OwnershipSeq> {
+        Replicate::query_one_mut(self, entity_id)?.owner = owner;
+
+        // the new owner counts its inputs from its own start, and nothing the old owner sent applies anymore
+        self.delete_component(entity_id, LastProcessedInput::COMPONENT_ID);
+        self.net_queues()
+            .inputs
+            .retain(|(server_entity_id, ..)| server_entity_id.0 != entity_id);
+
+        let seq = Ownership::query_one(self, entity_id)
+            .map_or(OwnershipSeq(1), |ownership| ownership.seq + OwnershipSeq(1));
+        self.set_component(
+            entity_id,
+            Ownership {
+                seq,
+                acknowledged: owner.is_none(),
+            },
+        );
+        Some(seq)
+    }
+
+    /// Whether a client owns an entity and has accepted its latest transfer, i.e. whether its writes and inputs for
+    /// the entity should be applied.
+    pub fn has_authority(&self, client_id: ClientId, entity_id: EntityId) -> bool {
+        Replicate::query_one(self, entity_id)
+            .is_some_and(|replicate| replicate.owner == Some(client_id))
+            && Ownership::query_one(self, entity_id).is_none_or(|ownership| ownership.acknowledged)
+    }
+
+    pub(crate) fn accept_ownership(
+        &mut self,
+        client_id: ClientId,
+        server_entity_id: ServerEntityId,
+        seq: OwnershipSeq,
+    ) {
+        let entity_id = server_entity_id.0;
+        if Replicate::query_one(self, entity_id)
+            .is_none_or(|replicate| replicate.owner != Some(client_id))
+        {
+            return;
+        }
+
+        // an acknowledgement of an earlier transfer says nothing about the current one
+        if let Some(ownership) = Ownership::query_one_mut(self, entity_id)
+            && ownership.seq == seq
+        {
+            ownership.acknowledged = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hydrogen_data_structures::selection::Selection;
+    use hydrogen_net::comm::TcpCommunicator;
+
+    use super::*;
+    use crate::{
+        component::SerializableComponent,
+        ecs_net::{
+            EcsReplicator, NetEcsCommand,
+            tests::{connect, deliver},
+        },
+        prediction::{InputTick, Prediction},
+        validation::WriteRejection,
+    };
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct OwnedPosition(f32);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct OwnedMove(f32);
+
+    const FIRST_OWNER: ClientId = ClientId(1);
+    const SECOND_OWNER: ClientId = ClientId(2);
+
+    fn move_by(world: &mut World, entity_id: EntityId, input: &dyn SerializableComponent) {
+        let distance = input.downcast_ref::<OwnedMove>().unwrap().0;
+        if let Some(position) = OwnedPosition::query_one_mut(world, entity_id) {
+            position.0 += distance;
+        }
+    }
+
+    fn predict_position() -> Prediction {
+        let mut predicted_components = Selection::none();
+        predicted_components.allow(OwnedPosition::COMPONENT_ID);
+        Prediction::new(predicted_components, move_by)
+    }
+
+    fn position(world: &World, entity_id: EntityId) -> f32 {
+        OwnedPosition::query_one(world, entity_id).unwrap().0
+    }
+
+    /// A client connected to the server, along with the server's end of the connection.
+    struct Peer {
+        client_id: ClientId,
+        world: World,
+        comm: TcpCommunicator,
+        replicator: EcsReplicator,
+        prediction: Prediction,
+        server_comm: TcpCommunicator,
+        server_replicator: EcsReplicator,
+    }
+
+    impl Peer {
+        fn new(client_id: ClientId) -> Self {
+            let (server_comm, comm) = connect();
+            Self {
+                client_id,
+                world: World::default(),
+                comm,
+                replicator: EcsReplicator::new(client_id),
+                prediction: predict_position(),
+                server_comm,
+                server_replicator: EcsReplicator::new(client_id),
+            }
+        }
+
+        fn receive(&mut self, server: &mut World) {
+            self.server_replicator
+                .server_update(server, &mut self.server_comm);
+            for command in deliver(&mut self.server_comm, &mut self.comm) {
+                self.prediction
+                    .execute_net_command(&mut self.world, command)
+                    .unwrap();
+            }
+        }
+
+        fn send(&mut self, server: &mut World) -> Vec<NetEcsCommand> {
+            self.replicator
+                .client_update(&mut self.world, &mut self.comm);
+            let sent = deliver(&mut self.comm, &mut self.server_comm);
+            for command in sent.iter().cloned() {
+                server
+                    .execute_client_net_command(self.client_id, command)
+                    .unwrap();
+            }
+            sent
+        }
+
+        fn input(&mut self, server_entity_id: ServerEntityId, distance: f32) {
+            self.prediction.input(
+                &mut self.world,
+                &mut self.comm,
+                server_entity_id,
+                OwnedMove(distance),
+            );
+        }
+    }
+
+    #[test]
+    fn ownership_moves_between_clients() {
+        let mut server = World::default();
+        let entity_id = server.spawn((OwnedPosition(0.0),));
+        let server_entity_id = ServerEntityId(entity_id);
+        server.set_component(
+            entity_id,
+            Replicate {
+                server_entity_id,
+                owner: Some(FIRST_OWNER),
+                replicate_to: Selection::all(),
+                client_writable: Selection::none(),
+                replicated_components: Selection::all(),
+                auto_replicate_changes: Selection::all(),
+            },
+        );
+
+        let mut first = Peer::new(FIRST_OWNER);
+        let mut second = Peer::new(SECOND_OWNER);
+        first.receive(&mut server);
+        second.receive(&mut server);
+        let first_entity_id = first.world.entity_id_from_server(server_entity_id);
+        let second_entity_id = second.world.entity_id_from_server(server_entity_id);
+
+        // the first owner moves the entity a few times
+        for _ in 0..3 {
+            first.input(server_entity_id, 1.0);
+        }
+        first.send(&mut server);
+        server.process_inputs(move_by);
+        assert_eq!(position(&server, entity_id), 3.0);
+
+        let seq = server.transfer_ownership(entity_id, Some(SECOND_OWNER));
+        assert_eq!(seq, Some(OwnershipSeq(1)));
+        assert!(!server.has_authority(FIRST_OWNER, entity_id));
+        assert!(!server.has_authority(SECOND_OWNER, entity_id));
+        first.receive(&mut server);
+        second.receive(&mut server);
+        assert_eq!(position(&first.world, first_entity_id), 3.0);
+        assert_eq!(position(&second.world, second_entity_id), 3.0);
+
+        // the old owner's inputs are refused as soon as the transfer happens
+        first.comm.send(NetEcsCommand::Input(
+            server_entity_id,
+            InputTick(100),
+            Box::new(OwnedMove(100.0)),
+        ));
+        first.send(&mut server);
+        server.process_inputs(move_by);
+        assert_eq!(position(&server, entity_id), 3.0);
+
+        // an acceptance of an older transfer doesn't count
+        server
+            .execute_client_net_command(
+                SECOND_OWNER,
+                NetEcsCommand::AcceptOwnership(server_entity_id, OwnershipSeq(0)),
+            )
+            .unwrap();
+        assert!(!server.has_authority(SECOND_OWNER, entity_id));
+
+        // neither does a write from before the new owner accepted
+        let rejected = server.rejected_client_writes();
+        server
+            .execute_client_net_command(
+                SECOND_OWNER,
+                NetEcsCommand::SetComponent(server_entity_id, Box::new(OwnedPosition(50.0))),
+            )
+            .unwrap();
+        assert_eq!(
+            rejected.recv_all()[0].reason,
+            WriteRejection::OwnershipPending
+        );
+
+        let accepted = second.send(&mut server);
+        assert!(
+            accepted.iter().any(|command| matches!(
+                command,
+                NetEcsCommand::AcceptOwnership(_, OwnershipSeq(1))
+            ))
+        );
+        assert!(server.has_authority(SECOND_OWNER, entity_id));
+
+        // the new owner's inputs count from its own start, below the old owner's, and are still applied
+        for _ in 0..2 {
+            second.input(server_entity_id, 1.0);
+        }
+        second.send(&mut server);
+        server.process_inputs(move_by);
+        assert_eq!(position(&server, entity_id), 5.0);
+
+        // and acknowledged, which settles the new owner's prediction on the server's state
+        first.receive(&mut server);
+        second.receive(&mut server);
+        assert_eq!(second.prediction.pending_inputs(server_entity_id), 0);
+        assert_eq!(position(&second.world, second_entity_id), 5.0);
+        assert_eq!(position(&first.world, first_entity_id), 5.0);
+    }
+}
@@ -0,0 +1,388 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt, mem,
+    sync::Arc,
+};
+
+use derive_more::*;
+use hydrogen_data_structures::selection::Selection;
+use hydrogen_net::comm::TcpCommunicator;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    component::{Component, ComponentId, SerializableComponent},
+    ecs_net::{NetEcsCommand, ServerEntityId},
+    entity::EntityId,
+    migration::MigrationError,
+    world::World,
+};
+
+mod hydrogen {
+    pub use crate as ecs;
+}
+
+/// The sequence number of an input sent by a client, counting up from 0.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    From,
+    Into,
+    Add,
+    AddAssign,
+    Sub,
+    SubAssign,
+)]
+pub struct InputTick(pub u32);
+
+/// Applies one input to an entity. The server and predicting clients must use the same function so that they agree.
+pub type ApplyInputFn = Arc<dyn Fn(&mut World, EntityId, &dyn SerializableComponent) + Send + Sync>;
+
+/// The last input the server has applied to an entity with [`World::process_inputs`]. Only used by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub struct LastProcessedInput(pub InputTick);
+
+impl World {
+    /// Applies the inputs received from the owners of entities since the last call, in the order they were received.
+    /// Inputs that are older than an entity's [`LastProcessedInput`] are dropped.
+    ///
+    /// The owner is told which inputs have been applied by its [`EcsReplicator`](crate::ecs_net::EcsReplicator), once
+    /// the state they led to has been sent.
+    pub fn process_inputs(
+        &mut self,
+        apply_input: impl Fn(&mut World, EntityId, &dyn SerializableComponent),
+    ) {
+        for (server_entity_id, input_tick, input) in mem::take(&mut self.net_queues().inputs) {
+            let entity_id = server_entity_id.0;
+            if LastProcessedInput::query_one(self, entity_id)
+                .is_some_and(|last_processed| last_processed.0 >= input_tick)
+            {
+                continue;
+            }
+
+            apply_input(self, entity_id, input.as_ref());
+            self.set_component(entity_id, LastProcessedInput(input_tick));
+        }
+    }
+}
+
+type PendingInputs = VecDeque<(InputTick, Box<dyn SerializableComponent>)>;
+
+/// Client-side prediction for the entities owned by this client.
+///
+/// Inputs are applied to the client's world straight away and sent to the server, which applies them with the same
+/// function in [`World::process_inputs`]. Whenever the server's values of the predicted components or an
+/// acknowledgement of some inputs arrive, those components are reset to the server's values and the inputs the server
+/// hasn't applied yet are applied again.
+///
+/// Predicted components should be replicated with [`Replicate::auto_replicate_changes`](crate::ecs_net::Replicate)
+/// and not be client-writable.
+pub struct Prediction {
+    apply_input: ApplyInputFn,
+    pub predicted_components: Selection<ComponentId>,
+    next_input_tick: InputTick,
+    pending_inputs: BTreeMap<ServerEntityId, PendingInputs>,
+    /// The server's values of the predicted components of every entity with inputs.
+    authoritative: BTreeMap<ServerEntityId, BTreeMap<ComponentId, Box<dyn SerializableComponent>>>,
+}
+
+impl fmt::Debug for Prediction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Prediction")
+            .field("predicted_components", &self.predicted_components)
+            .field("next_input_tick", &self.next_input_tick)
+            .field("pending_inputs", &self.pending_inputs)
+            .field("authoritative", &self.authoritative)
+            .finish()
+    }
+}
+
+impl Prediction {
+    pub fn new(
+        predicted_components: Selection<ComponentId>,
+        apply_input: impl Fn(&mut World, EntityId, &dyn SerializableComponent) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            apply_input: Arc::new(apply_input),
+            predicted_components,
+            next_input_tick: InputTick(0),
+            pending_inputs: Default::default(),
+            authoritative: Default::default(),
+        }
+    }
+
+    /// The number of inputs for an entity that the server hasn't acknowledged yet.
+    pub fn pending_inputs(&self, server_entity_id: ServerEntityId) -> usize {
+        self.pending_inputs
+            .get(&server_entity_id)
+            .map_or(0, VecDeque::len)
+    }
+
+    /// Applies an input to an owned entity and sends it to the server.
+    pub fn input(
+        &mut self,
+        world: &mut World,
+        comm: &mut TcpCommunicator,
+        server_entity_id: ServerEntityId,
+        input: impl SerializableComponent,
+    ) -> InputTick {
+        let entity_id = world.entity_id_from_server(server_entity_id);
+
+        // without any pending inputs, the world has the server's values
+        if self.pending_inputs(server_entity_id) == 0 {
+            let authoritative = world
+                .get_all_serializable_components(entity_id)
+                .filter(|(component_id, _)| self.predicted_components.contains(component_id))
+                .map(|(component_id, component)| (component_id, component.clone_box()))
+                .collect();
+            self.authoritative.insert(server_entity_id, authoritative);
+        }
+
+        let input_tick = self.next_input_tick;
+        self.next_input_tick += InputTick(1);
+
+        (self.apply_input)(world, entity_id, &input);
+        comm.send(NetEcsCommand::Input(
+            server_entity_id,
+            input_tick,
+            input.clone_box(),
+        ));
+        self.pending_inputs
+            .entry(server_entity_id)
+            .or_default()
+            .push_back((input_tick, Box::new(input)));
+
+        input_tick
+    }
+
+    fn is_predicted(&self, server_entity_id: ServerEntityId, component_id: ComponentId) -> bool {
+        self.authoritative.contains_key(&server_entity_id)
+            && self.predicted_components.contains(&component_id)
+    }
+
+    /// Resets the predicted components of an entity to the server's values and applies the pending inputs again.
+    fn reconcile(&mut self, world: &mut World, server_entity_id: ServerEntityId) {
+        let entity_id = world.entity_id_from_server(server_entity_id);
+
+        if let Some(authoritative) = self.authoritative.get(&server_entity_id) {
+            for component in authoritative.values() {
+                world.set_component_boxed(entity_id, component.clone_box());
+            }
+        }
+
+        for (_, input) in self
+            .pending_inputs
+            .get(&server_entity_id)
+            .into_iter()
+            .flatten()
+        {
+            (self.apply_input)(world, entity_id, input.as_ref());
+        }
+
+        if self.pending_inputs(server_entity_id) == 0 {
+            self.pending_inputs.remove(&server_entity_id);
+            self.authoritative.remove(&server_entity_id);
+        }
+    }
+
+    /// Use instead of [`World::execute_net_command`] for commands from the server, so that the values of predicted
+    /// components are kept track of and reconciled. To interpolate other entities as well, use a
+    /// [`ClientNet`](crate::client_net::ClientNet).
+    pub fn execute_net_command(
+        &mut self,
+        world: &mut World,
+        command: NetEcsCommand,
+    ) -> Result<(), MigrationError> {
+        if let NetEcsCommand::Batch(commands) = command {
+            for command in world.migrate_net_commands(commands)? {
+                self.execute_net_command(world, command)?;
+            }
+            return Ok(());
+        }
+
+        if let Some(command) = self.handle_net_command(world, command)? {
+            world.execute_net_command(command)?;
+        }
+        Ok(())
+    }
+
+    /// Takes the commands that only concern prediction, and keeps track of the ones that change which entities and
+    /// components are predicted before handing them back to be applied.
+    pub(crate) fn handle_net_command(
+        &mut self,
+        world: &mut World,
+        command: NetEcsCommand,
+    ) -> Result<Option<NetEcsCommand>, MigrationError> {
+        match command {
+            NetEcsCommand::SetComponent(server_entity_id, component)
+                if self.is_predicted(server_entity_id, component.component_id()) =>
+            {
+                let component = world.migrations().migrate(component)?;
+                if let Some(authoritative) = self.authoritative.get_mut(&server_entity_id) {
+                    authoritative.insert(component.component_id(), component);
+                }
+                self.reconcile(world, server_entity_id);
+            }
+            NetEcsCommand::SetComponentDelta(server_entity_id, delta)
+                if self.is_predicted(server_entity_id, delta.component_id) =>
+            {
+                let component = self
+                    .authoritative
+                    .get(&server_entity_id)
+                    .and_then(|authoritative| authoritative.get(&delta.component_id))
+                    .and_then(|base| delta.apply(base.as_ref()).ok());
+
+                match component {
+                    Some(component) => {
+                        let component = world.migrations().migrate(component)?;
+                        if let Some(authoritative) = self.authoritative.get_mut(&server_entity_id) {
+                            authoritative.insert(component.component_id(), component);
+                        }
+                        self.reconcile(world, server_entity_id);
+                    }
+                    None => world
+                        .net_queues()
+                        .outgoing_requests
+                        .push((server_entity_id, delta.component_id)),
+                }
+            }
+            NetEcsCommand::InputAck(server_entity_id, input_tick) => {
+                if let Some(pending_inputs) = self.pending_inputs.get_mut(&server_entity_id) {
+                    pending_inputs
+                        .retain(|&(pending_input_tick, _)| pending_input_tick > input_tick);
+                    self.reconcile(world, server_entity_id);
+                }
+            }
+            NetEcsCommand::DeleteComponent(server_entity_id, component_id) => {
+                if let Some(authoritative) = self.authoritative.get_mut(&server_entity_id) {
+                    authoritative.remove(&component_id);
+                }
+                return Ok(Some(command));
+            }
+            // a despawned or respawned entity starts over from the server's values
+            NetEcsCommand::DeleteEntity(server_entity_id)
+            | NetEcsCommand::SpawnEntity(server_entity_id, _) => {
+                self.pending_inputs.remove(&server_entity_id);
+                self.authoritative.remove(&server_entity_id);
+                return Ok(Some(command));
+            }
+            // the server's state as of a transfer already has every input it will ever apply from the old owner
+            NetEcsCommand::TransferOwnership(server_entity_id, ..) => {
+                self.pending_inputs.remove(&server_entity_id);
+                self.reconcile(world, server_entity_id);
+                return Ok(Some(command));
+            }
+            command => return Ok(Some(command)),
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hydrogen_data_structures::selection::Selection;
+
+    use super::*;
+    use crate::{component::SerializableComponentBundle, ecs_net::tests::connect};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct PredictedPosition(f32);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct PredictedMove(f32);
+
+    fn move_by(world: &mut World, entity_id: EntityId, input: &dyn SerializableComponent) {
+        let distance = input.downcast_ref::<PredictedMove>().unwrap().0;
+        if let Some(position) = PredictedPosition::query_one_mut(world, entity_id) {
+            position.0 += distance;
+        }
+    }
+
+    #[test]
+    fn corrections_rewind_and_replay_unacknowledged_inputs() {
+        let (_server_comm, mut comm) = connect();
+        let mut world = World::default();
+        let mut predicted_components = Selection::none();
+        predicted_components.allow(PredictedPosition::COMPONENT_ID);
+        let mut prediction = Prediction::new(predicted_components, move_by);
+
+        let server_entity_id = ServerEntityId(EntityId(0));
+        let mut bundle = SerializableComponentBundle::new();
+        bundle.set_component(PredictedPosition(0.0));
+        prediction
+            .execute_net_command(
+                &mut world,
+                NetEcsCommand::SpawnEntity(server_entity_id, bundle),
+            )
+            .unwrap();
+        let entity_id = world.entity_id_from_server(server_entity_id);
+        let position = |world: &World| PredictedPosition::query_one(world, entity_id).unwrap().0;
+
+        for _ in 0..3 {
+            prediction.input(&mut world, &mut comm, server_entity_id, PredictedMove(1.0));
+        }
+        assert_eq!(position(&world), 3.0);
+        assert_eq!(prediction.pending_inputs(server_entity_id), 3);
+
+        // the server disagrees about where the first input led, so every input is applied again on top of its value
+        prediction
+            .execute_net_command(
+                &mut world,
+                NetEcsCommand::SetComponent(server_entity_id, Box::new(PredictedPosition(10.0))),
+            )
+            .unwrap();
+        assert_eq!(position(&world), 13.0);
+
+        // once it has applied the first input, only the other two are applied again
+        prediction
+            .execute_net_command(
+                &mut world,
+                NetEcsCommand::InputAck(server_entity_id, InputTick(0)),
+            )
+            .unwrap();
+        assert_eq!(prediction.pending_inputs(server_entity_id), 2);
+        assert_eq!(position(&world), 12.0);
+
+        // inputs made while the server's reply was on its way are kept
+        prediction.input(&mut world, &mut comm, server_entity_id, PredictedMove(1.0));
+        assert_eq!(position(&world), 13.0);
+        prediction
+            .execute_net_command(
+                &mut world,
+                NetEcsCommand::Batch(vec![
+                    NetEcsCommand::SetComponent(
+                        server_entity_id,
+                        Box::new(PredictedPosition(12.0)),
+                    ),
+                    NetEcsCommand::InputAck(server_entity_id, InputTick(2)),
+                ]),
+            )
+            .unwrap();
+        assert_eq!(prediction.pending_inputs(server_entity_id), 1);
+        assert_eq!(position(&world), 13.0);
+
+        // with every input acknowledged, the world has the server's value
+        prediction
+            .execute_net_command(
+                &mut world,
+                NetEcsCommand::SetComponent(server_entity_id, Box::new(PredictedPosition(14.0))),
+            )
+            .unwrap();
+        prediction
+            .execute_net_command(
+                &mut world,
+                NetEcsCommand::InputAck(server_entity_id, InputTick(3)),
+            )
+            .unwrap();
+        assert_eq!(prediction.pending_inputs(server_entity_id), 0);
+        assert_eq!(position(&world), 14.0);
+    }
+}
@@ -0,0 +1,523 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use thiserror::Error;
+
+use crate::{
+    component::{SerializableComponent, SerializableComponentBundle},
+    entity::EntityId,
+    migration::MigrationError,
+    world::World,
+};
+
+#[derive(Debug, Error)]
+pub enum PrefabError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unsupported prefab file {0:?}, expected a .ron or .json file")]
+    UnsupportedFile(String),
+    #[error("RON serialization error: {0}")]
+    RonSerialize(#[from] ron::Error),
+    #[error("RON deserialization error: {0}")]
+    RonDeserialize(#[from] ron::error::SpannedError),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("no prefab named {0:?}")]
+    NotFound(String),
+    #[error("prefab {0:?} inherits from itself")]
+    InheritanceCycle(String),
+    #[error("migration error: {0}")]
+    Migration(#[from] MigrationError),
+}
+
+/// A component in a prefab file that couldn't be deserialized, usually because its type isn't linked into the program
+/// or its fields don't match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentLoadError {
+    /// The position of the component in the file's component list.
+    pub index: usize,
+    /// The name the component was tagged with, if there was one.
+    pub tag: Option<String>,
+    pub message: String,
+}
+
+/// The result of loading a prefab file. Components that failed to deserialize are left out of the prefab.
+#[derive(Debug, Clone)]
+pub struct LoadedPrefab {
+    pub prefab: Prefab,
+    pub failed_components: Vec<ComponentLoadError>,
+}
+
+/// A template for spawning entities, optionally inheriting the components of a base prefab in a [`PrefabLibrary`].
+///
+/// In files, components are listed by their serialized name:
+///
+/// ```ron
+/// (
+///     base: Some("enemy"),
+///     components: [
+///         { "Health": (current: 50, max: 50) },
+///     ],
+/// )
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Prefab {
+    pub base: Option<String>,
+    pub components: SerializableComponentBundle,
+}
+
+#[derive(Serialize)]
+struct PrefabFileRef<'a> {
+    base: &'a Option<String>,
+    components: Vec<&'a dyn SerializableComponent>,
+}
+
+/// Components are kept as untyped values at first so that each one can fail to deserialize on its own.
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "V: DeserializeOwned"))]
+struct PrefabFile<V> {
+    #[serde(default)]
+    base: Option<String>,
+    #[serde(default)]
+    components: Vec<V>,
+}
+
+trait ComponentValue: DeserializeOwned {
+    fn tag(&self) -> Option<String>;
+    fn into_component(self) -> Result<Box<dyn SerializableComponent>, String>;
+}
+
+impl ComponentValue for ron::Value {
+    fn tag(&self) -> Option<String> {
+        match self {
+            ron::Value::Map(map) => match map.keys().next()? {
+                ron::Value::String(tag) => Some(tag.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn into_component(self) -> Result<Box<dyn SerializableComponent>, String> {
+        Box::<dyn SerializableComponent>::deserialize(self).map_err(|e| e.to_string())
+    }
+}
+
+impl ComponentValue for serde_json::Value {
+    fn tag(&self) -> Option<String> {
+        self.as_object()?.keys().next().cloned()
+    }
+
+    fn into_component(self) -> Result<Box<dyn SerializableComponent>, String> {
+        Box::<dyn SerializableComponent>::deserialize(self).map_err(|e| e.to_string())
+    }
+}
+
+impl<V: ComponentValue> PrefabFile<V> {
+    fn load(self) -> LoadedPrefab {
+        let mut prefab = Prefab {
+            base: self.base,
+            ..Default::default()
+        };
+        let mut failed_components = Vec::new();
+
+        for (index, value) in self.components.into_iter().enumerate() {
+            let tag = value.tag();
+            match value.into_component() {
+                Ok(component) => {
+                    prefab.components.set_component_boxed(component);
+                }
+                Err(message) => failed_components.push(ComponentLoadError {
+                    index,
+                    tag,
+                    message,
+                }),
+            }
+        }
+
+        LoadedPrefab {
+            prefab,
+            failed_components,
+        }
+    }
+}
+
+impl Prefab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_base(mut self, base: impl Into<String>) -> Self {
+        self.base = Some(base.into());
+        self
+    }
+
+    pub fn with<T: SerializableComponent>(mut self, component: T) -> Self {
+        self.components.set_component(component);
+        self
+    }
+
+    pub fn from_ron_str(ron: &str) -> Result<LoadedPrefab, PrefabError> {
+        Ok(ron::from_str::<PrefabFile<ron::Value>>(ron)?.load())
+    }
+
+    pub fn from_json_str(json: &str) -> Result<LoadedPrefab, PrefabError> {
+        Ok(serde_json::from_str::<PrefabFile<serde_json::Value>>(json)?.load())
+    }
+
+    /// Loads a `.ron` or `.json` prefab file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<LoadedPrefab, PrefabError> {
+        let path = path.as_ref();
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("ron") => Self::from_ron_str(&fs::read_to_string(path)?),
+            Some("json") => Self::from_json_str(&fs::read_to_string(path)?),
+            _ => Err(PrefabError::UnsupportedFile(path.display().to_string())),
+        }
+    }
+
+    fn as_file(&self) -> PrefabFileRef<'_> {
+        PrefabFileRef {
+            base: &self.base,
+            components: self
+                .components
+                .iter()
+                .map(|(_, component)| component.as_ref())
+                .collect(),
+        }
+    }
+
+    pub fn to_ron_string(&self) -> Result<String, PrefabError> {
+        Ok(ron::ser::to_string_pretty(
+            &self.as_file(),
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    pub fn to_json_string(&self) -> Result<String, PrefabError> {
+        Ok(serde_json::to_string_pretty(&self.as_file())?)
+    }
+}
+
+/// A set of named prefabs that can inherit from each other.
+#[derive(Debug, Clone, Default)]
+pub struct PrefabLibrary {
+    prefabs: BTreeMap<String, Prefab>,
+}
+
+impl PrefabLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, prefab: Prefab) -> Option<Prefab> {
+        self.prefabs.insert(name.into(), prefab)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Prefab> {
+        self.prefabs.remove(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.prefabs.keys().map(String::as_str)
+    }
+
+    /// Loads a prefab file and adds it to the library, named after the file without its extension. Returns the
+    /// components that failed to deserialize.
+    pub fn load_file(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<Vec<ComponentLoadError>, PrefabError> {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| PrefabError::UnsupportedFile(path.display().to_string()))?;
+
+        let LoadedPrefab {
+            prefab,
+            failed_components,
+        } = Prefab::from_file(path)?;
+        self.insert(name, prefab);
+
+        Ok(failed_components)
+    }
+
+    /// Combines the components of a prefab with those of its bases. Components of a prefab replace those of the same
+    /// type in its base.
+    pub fn resolve(&self, name: &str) -> Result<SerializableComponentBundle, PrefabError> {
+        let mut chain = Vec::new();
+        let mut current = Some(name);
+        while let Some(name) = current {
+            let prefab = self
+                .get(name)
+                .ok_or_else(|| PrefabError::NotFound(name.to_string()))?;
+            if chain.iter().any(|(chain_name, _)| *chain_name == name) {
+                return Err(PrefabError::InheritanceCycle(name.to_string()));
+            }
+
+            chain.push((name, prefab));
+            current = prefab.base.as_deref();
+        }
+
+        let mut components = SerializableComponentBundle::new();
+        for (_, prefab) in chain.into_iter().rev() {
+            for (_, component) in prefab.components.iter() {
+                components.set_component_boxed(component.clone());
+            }
+        }
+
+        Ok(components)
+    }
+
+    /// Spawns an entity from a prefab, with `overrides` replacing or adding to its components. Components from older
+    /// versions are upgraded with the world's [`migrations`](World::migrations) first.
+    pub fn instantiate(
+        &self,
+        world: &mut World,
+        name: &str,
+        overrides: SerializableComponentBundle,
+    ) -> Result<EntityId, PrefabError> {
+        let mut components = self.resolve(name)?;
+        for component in overrides.into_serializable_components() {
+            components.set_component_boxed(component);
+        }
+
+        let mut migrated_components = SerializableComponentBundle::new();
+        for component in components.into_serializable_components() {
+            migrated_components.set_component_boxed(world.migrations().migrate(component)?);
+        }
+
+        Ok(world.spawn(migrated_components))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct PrefabHealth {
+        current: u32,
+        max: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct PrefabSpeed {
+        tiles: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    #[component(id = "prefab_score")]
+    struct OldPrefabScore {
+        points: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    #[component(id = "prefab_score", version = 1)]
+    struct PrefabScore {
+        points: i64,
+    }
+
+    fn health(current: u32) -> PrefabHealth {
+        PrefabHealth { current, max: 50 }
+    }
+
+    fn assert_loaded(loaded: LoadedPrefab) {
+        let LoadedPrefab {
+            prefab,
+            failed_components,
+        } = loaded;
+        assert_eq!(
+            prefab,
+            Prefab::new()
+                .with_base("enemy")
+                .with(health(20))
+                .with(PrefabSpeed { tiles: 2 })
+        );
+
+        // the components that can't be read are reported, and the rest are kept
+        let failed = failed_components
+            .iter()
+            .map(|error| (error.index, error.tag.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(failed, [(1, Some("Unknown")), (2, Some("PrefabSpeed"))]);
+    }
+
+    #[test]
+    fn prefabs_are_loaded_from_ron() {
+        let loaded = Prefab::from_ron_str(
+            r#"(
+                base: Some("enemy"),
+                components: [
+                    { "PrefabHealth": (current: 20, max: 50) },
+                    { "Unknown": (value: 1) },
+                    { "PrefabSpeed": (tiles: "fast") },
+                    { "PrefabSpeed": (tiles: 2) },
+                ],
+            )"#,
+        )
+        .unwrap();
+        assert_loaded(loaded);
+
+        let prefab = Prefab::new().with_base("enemy").with(health(20));
+        let round_trip = Prefab::from_ron_str(&prefab.to_ron_string().unwrap()).unwrap();
+        assert_eq!(round_trip.prefab, prefab);
+        assert!(round_trip.failed_components.is_empty());
+
+        assert!(matches!(
+            Prefab::from_ron_str("(components: 1)"),
+            Err(PrefabError::RonDeserialize(_))
+        ));
+    }
+
+    #[test]
+    fn prefabs_are_loaded_from_json() {
+        let loaded = Prefab::from_json_str(
+            r#"{
+                "base": "enemy",
+                "components": [
+                    { "PrefabHealth": { "current": 20, "max": 50 } },
+                    { "Unknown": { "value": 1 } },
+                    { "PrefabSpeed": { "tiles": "fast" } },
+                    { "PrefabSpeed": { "tiles": 2 } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_loaded(loaded);
+
+        let prefab = Prefab::new().with(health(20));
+        let round_trip = Prefab::from_json_str(&prefab.to_json_string().unwrap()).unwrap();
+        assert_eq!(round_trip.prefab, prefab);
+
+        assert!(matches!(
+            Prefab::from_file("enemy.toml"),
+            Err(PrefabError::UnsupportedFile(_))
+        ));
+    }
+
+    #[test]
+    fn prefabs_override_their_bases() {
+        let mut library = PrefabLibrary::new();
+        library.insert(
+            "enemy",
+            Prefab::new()
+                .with(health(50))
+                .with(PrefabSpeed { tiles: 1 }),
+        );
+        library.insert(
+            "boss",
+            Prefab::new().with_base("enemy").with(PrefabHealth {
+                current: 500,
+                max: 500,
+            }),
+        );
+        library.insert(
+            "fast_boss",
+            Prefab::new()
+                .with_base("boss")
+                .with(PrefabSpeed { tiles: 3 }),
+        );
+
+        let components = library.resolve("fast_boss").unwrap();
+        assert_eq!(components.len(), 2);
+        assert_eq!(
+            components
+                .get_component(PrefabHealth::COMPONENT_ID)
+                .unwrap()
+                .downcast_ref(),
+            Some(&PrefabHealth {
+                current: 500,
+                max: 500
+            })
+        );
+
+        // overrides replace the components of every prefab in the chain
+        let mut world = World::default();
+        let mut overrides = SerializableComponentBundle::new();
+        overrides.set_component(health(1));
+        let entity_id = library
+            .instantiate(&mut world, "fast_boss", overrides)
+            .unwrap();
+        assert_eq!(PrefabHealth::query_one(&world, entity_id), Some(&health(1)));
+        assert_eq!(
+            PrefabSpeed::query_one(&world, entity_id),
+            Some(&PrefabSpeed { tiles: 3 })
+        );
+    }
+
+    #[test]
+    fn broken_inheritance_is_refused() {
+        let mut library = PrefabLibrary::new();
+        library.insert("a", Prefab::new().with_base("b"));
+        library.insert("b", Prefab::new().with_base("a"));
+        library.insert("c", Prefab::new().with_base("c"));
+        library.insert("d", Prefab::new().with_base("missing"));
+
+        assert!(matches!(
+            library.resolve("a"),
+            Err(PrefabError::InheritanceCycle(name)) if name == "a"
+        ));
+        assert!(matches!(
+            library.resolve("c"),
+            Err(PrefabError::InheritanceCycle(name)) if name == "c"
+        ));
+        assert!(matches!(
+            library.resolve("d"),
+            Err(PrefabError::NotFound(name)) if name == "missing"
+        ));
+
+        let mut world = World::default();
+        assert!(
+            library
+                .instantiate(&mut world, "a", SerializableComponentBundle::new())
+                .is_err()
+        );
+        assert_eq!(world.entities().count(), 0);
+    }
+
+    #[test]
+    fn old_components_are_migrated_when_instantiated() {
+        let mut library = PrefabLibrary::new();
+        let loaded =
+            Prefab::from_ron_str(r#"(components: [{ "prefab_score": (points: 4) }])"#).unwrap();
+        assert!(loaded.failed_components.is_empty());
+        library.insert("scored", loaded.prefab);
+
+        // nothing is spawned if a component can't be brought up to date
+        let mut world = World::default();
+        let error = library
+            .instantiate(&mut world, "scored", SerializableComponentBundle::new())
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            PrefabError::Migration(MigrationError::MissingMigration {
+                from_version: 0,
+                current_version: 1,
+                ..
+            })
+        ));
+        assert_eq!(world.entities().count(), 0);
+
+        world
+            .migrations_mut()
+            .register(|old: OldPrefabScore| PrefabScore {
+                points: old.points as i64 * 100,
+            });
+        let entity_id = library
+            .instantiate(&mut world, "scored", SerializableComponentBundle::new())
+            .unwrap();
+        assert_eq!(
+            PrefabScore::query_one(&world, entity_id),
+            Some(&PrefabScore { points: 400 })
+        );
+    }
+}
@@ -0,0 +1,513 @@
+use std::{
+    any::{Any, TypeId},
+    collections::BTreeMap,
+    fmt,
+    sync::OnceLock,
+};
+
+use cgmath::{Matrix4, Quaternion, Vector2, Vector3, Vector4};
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+use crate::{
+    change_detection::Tick,
+    component::{Component, ComponentId},
+    ecs_net::ServerEntityId,
+    entity::EntityId,
+    registry::inventory,
+    world::World,
+};
+
+pub use hydrogen_ecs_proc_macro::Reflect;
+
+#[derive(Debug, Error)]
+pub enum ReflectError {
+    #[error("entity has no component named {0}")]
+    NoSuchComponent(String),
+    #[error("{0} doesn't implement Reflect")]
+    NotReflectable(&'static str),
+    #[error("no field at {0}")]
+    NoSuchField(String),
+    #[error("{0} can't be set directly, set its fields instead")]
+    NotAValue(&'static str),
+    #[error("couldn't parse {value:?} as {type_name}: {message}")]
+    Parse {
+        type_name: &'static str,
+        value: String,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldInfo {
+    /// The field's name, or its index for tuple structs and lists.
+    pub name: String,
+    pub type_name: &'static str,
+}
+
+/// Dynamic access to the fields of a type, for inspectors and consoles. Derive it with `#[derive(Reflect)]`, skipping
+/// fields whose types don't implement it with `#[reflect(skip)]`.
+///
+/// Types without fields, like numbers and strings, are values that can be set from a string with
+/// [`Reflect::set_from_str`].
+pub trait Reflect: fmt::Debug + Any + Send + Sync {
+    fn type_name(&self) -> &'static str;
+
+    fn fields(&self) -> Vec<FieldInfo> {
+        Vec::new()
+    }
+
+    fn field(&self, _name: &str) -> Option<&dyn Reflect> {
+        None
+    }
+
+    fn field_mut(&mut self, _name: &str) -> Option<&mut dyn Reflect> {
+        None
+    }
+
+    /// Parses `value` as RON and assigns it.
+    fn set_from_str(&mut self, _value: &str) -> Result<(), ReflectError> {
+        Err(ReflectError::NotAValue(self.type_name()))
+    }
+}
+
+impl dyn Reflect {
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref()
+    }
+
+    pub fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+        (self as &mut dyn Any).downcast_mut()
+    }
+
+    /// Follows a `.`-separated path of field names, e.g. `translation.x`. An empty path returns `self`.
+    pub fn path(&self, path: &str) -> Result<&dyn Reflect, ReflectError> {
+        let mut current = self;
+        for name in path.split('.').filter(|name| !name.is_empty()) {
+            current = current
+                .field(name)
+                .ok_or_else(|| ReflectError::NoSuchField(path.to_string()))?;
+        }
+        Ok(current)
+    }
+
+    pub fn path_mut(&mut self, path: &str) -> Result<&mut dyn Reflect, ReflectError> {
+        let mut current = self;
+        for name in path.split('.').filter(|name| !name.is_empty()) {
+            current = current
+                .field_mut(name)
+                .ok_or_else(|| ReflectError::NoSuchField(path.to_string()))?;
+        }
+        Ok(current)
+    }
+
+    pub fn set_path(&mut self, path: &str, value: &str) -> Result<(), ReflectError> {
+        self.path_mut(path)?.set_from_str(value)
+    }
+
+    /// Every value nested inside this one, as `(path, value)` pairs with paths prefixed by `prefix`.
+    pub fn flatten(&self, prefix: &str) -> Vec<(String, String)> {
+        let mut values = Vec::new();
+        self.flatten_into(prefix.to_string(), &mut values);
+        values
+    }
+
+    fn flatten_into(&self, path: String, values: &mut Vec<(String, String)>) {
+        let fields = self.fields();
+        if fields.is_empty() {
+            values.push((path, format!("{self:?}")));
+            return;
+        }
+
+        for FieldInfo { name, .. } in fields {
+            if let Some(field) = self.field(&name) {
+                let field_path = if path.is_empty() {
+                    name
+                } else {
+                    format!("{path}.{name}")
+                };
+                field.flatten_into(field_path, values);
+            }
+        }
+    }
+}
+
+/// Lets a [`dyn Component`](Component) be viewed as a [`dyn Reflect`](Reflect). Submitted by the `Reflect` derive.
+#[derive(Debug, Clone, Copy)]
+pub struct ReflectRegistration {
+    type_id: fn() -> TypeId,
+    downcast_ref: fn(&dyn Any) -> Option<&dyn Reflect>,
+    downcast_mut: fn(&mut dyn Any) -> Option<&mut dyn Reflect>,
+}
+
+inventory::collect!(ReflectRegistration);
+
+impl ReflectRegistration {
+    pub const fn of<T: Reflect>() -> Self {
+        Self {
+            type_id: TypeId::of::<T>,
+            downcast_ref: downcast_reflect_ref::<T>,
+            downcast_mut: downcast_reflect_mut::<T>,
+        }
+    }
+
+    fn get(type_id: TypeId) -> Option<&'static Self> {
+        static REGISTRATIONS: OnceLock<BTreeMap<TypeId, &'static ReflectRegistration>> =
+            OnceLock::new();
+        REGISTRATIONS
+            .get_or_init(|| {
+                inventory::iter::<ReflectRegistration>
+                    .into_iter()
+                    .map(|registration| ((registration.type_id)(), registration))
+                    .collect()
+            })
+            .get(&type_id)
+            .copied()
+    }
+}
+
+fn downcast_reflect_ref<T: Reflect>(any: &dyn Any) -> Option<&dyn Reflect> {
+    any.downcast_ref::<T>().map(|value| value as &dyn Reflect)
+}
+
+fn downcast_reflect_mut<T: Reflect>(any: &mut dyn Any) -> Option<&mut dyn Reflect> {
+    any.downcast_mut::<T>()
+        .map(|value| value as &mut dyn Reflect)
+}
+
+impl dyn Component {
+    /// Returns `None` if the component's type doesn't derive [`Reflect`].
+    pub fn as_reflect(&self) -> Option<&dyn Reflect> {
+        let any = self.any_ref();
+        (ReflectRegistration::get(any.type_id())?.downcast_ref)(any)
+    }
+
+    pub fn as_reflect_mut(&mut self) -> Option<&mut dyn Reflect> {
+        let any = self as &mut dyn Any;
+        (ReflectRegistration::get((*any).type_id())?.downcast_mut)(any)
+    }
+}
+
+impl World {
+    /// Every component of the entity that implements [`Reflect`].
+    pub fn reflect_components(
+        &self,
+        entity_id: EntityId,
+    ) -> impl Iterator<Item = (ComponentId, &dyn Reflect)> {
+        self.get_all_components(entity_id)
+            .filter_map(|(component_id, component)| Some((component_id, component.as_reflect()?)))
+    }
+
+    /// Looks up a value by a path that starts with the component's display name, e.g. `Health.current`.
+    pub fn reflect_path(
+        &self,
+        entity_id: EntityId,
+        path: &str,
+    ) -> Result<&dyn Reflect, ReflectError> {
+        let (component_id, field_path) = self.resolve_reflect_path(entity_id, path)?;
+        let component = self.get_component(entity_id, component_id).unwrap();
+        component
+            .as_reflect()
+            .ok_or(ReflectError::NotReflectable(component.display_name()))?
+            .path(field_path)
+    }
+
+    /// Sets the value at `path` (see [`World::reflect_path`]) by parsing `value` as RON, marking the component as
+    /// changed.
+    ///
+    /// Serializable components are copied, changed and set again with [`World::set_component_boxed`], so that hooks,
+    /// the name index and the hierarchy see the new value. Other components can't be copied and are changed in place,
+    /// without running replace hooks.
+    pub fn set_reflect_path(
+        &mut self,
+        entity_id: EntityId,
+        path: &str,
+        value: &str,
+    ) -> Result<(), ReflectError> {
+        let (component_id, field_path) = self.resolve_reflect_path(entity_id, path)?;
+        let component = self.get_component(entity_id, component_id).unwrap();
+        let Some(serializable) = component.as_serializable() else {
+            // check the path first so that the component isn't marked as changed if it's invalid
+            self.reflect_path(entity_id, path)?;
+            let component = self.get_component_mut(entity_id, component_id).unwrap();
+            return component
+                .as_reflect_mut()
+                .unwrap()
+                .set_path(field_path, value);
+        };
+
+        let mut component: Box<dyn Component> = serializable.clone_box();
+        let display_name = component.display_name();
+        component
+            .as_reflect_mut()
+            .ok_or(ReflectError::NotReflectable(display_name))?
+            .set_path(field_path, value)?;
+        self.set_component_boxed(entity_id, component);
+        Ok(())
+    }
+
+    /// Every value in every component of the entity as `(path, value)` pairs, e.g. `("Health.current", "50")`.
+    /// Components that don't implement [`Reflect`] are listed whole under their display name.
+    pub fn dump_entity(&self, entity_id: EntityId) -> Vec<(String, String)> {
+        let mut values = Vec::new();
+        for (_, component) in self.get_all_components(entity_id) {
+            match component.as_reflect() {
+                Some(reflect) => values.extend(reflect.flatten(component.display_name())),
+                None => values.push((
+                    component.display_name().to_string(),
+                    format!("{component:?}"),
+                )),
+            }
+        }
+        values
+    }
+
+    fn resolve_reflect_path<'p>(
+        &self,
+        entity_id: EntityId,
+        path: &'p str,
+    ) -> Result<(ComponentId, &'p str), ReflectError> {
+        let (component_name, field_path) = path.split_once('.').unwrap_or((path, ""));
+        let component_id = self
+            .get_all_components(entity_id)
+            .find(|(_, component)| component.display_name() == component_name)
+            .map(|(component_id, _)| component_id)
+            .ok_or_else(|| ReflectError::NoSuchComponent(component_name.to_string()))?;
+
+        Ok((component_id, field_path))
+    }
+}
+
+fn parse_value<T: DeserializeOwned>(value: &str) -> Result<T, ReflectError> {
+    ron::from_str(value).map_err(|e| ReflectError::Parse {
+        type_name: std::any::type_name::<T>(),
+        value: value.to_string(),
+        message: e.to_string(),
+    })
+}
+
+macro_rules! impl_reflect_for_value {
+    ($($ty:ty),*) => {
+        $(
+            impl Reflect for $ty {
+                fn type_name(&self) -> &'static str {
+                    std::any::type_name::<Self>()
+                }
+
+                fn set_from_str(&mut self, value: &str) -> Result<(), ReflectError> {
+                    *self = parse_value(value)?;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_reflect_for_value!(
+    bool,
+    char,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    f32,
+    f64,
+    EntityId,
+    ServerEntityId,
+    ComponentId,
+    Tick
+);
+
+impl Reflect for String {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Accepts both quoted RON strings and bare text.
+    fn set_from_str(&mut self, value: &str) -> Result<(), ReflectError> {
+        *self = parse_value(value).unwrap_or_else(|_| value.to_string());
+        Ok(())
+    }
+}
+
+impl<T: Reflect> Reflect for Vec<T> {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    fn fields(&self) -> Vec<FieldInfo> {
+        (0..self.len())
+            .map(|index| FieldInfo {
+                name: index.to_string(),
+                type_name: std::any::type_name::<T>(),
+            })
+            .collect()
+    }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        Some(self.get(name.parse::<usize>().ok()?)?)
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        Some(self.get_mut(name.parse::<usize>().ok()?)?)
+    }
+}
+
+/// `Some` values have a single field named `0`.
+impl<T: Reflect> Reflect for Option<T> {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    fn fields(&self) -> Vec<FieldInfo> {
+        self.iter()
+            .map(|_| FieldInfo {
+                name: "0".to_string(),
+                type_name: std::any::type_name::<T>(),
+            })
+            .collect()
+    }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        match name {
+            "0" => Some(self.as_ref()?),
+            _ => None,
+        }
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        match name {
+            "0" => Some(self.as_mut()?),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_reflect_for_math {
+    ($ty:ident<$param:ident> { $($field:ident: $field_ty:ty),* }) => {
+        impl<$param: Reflect> Reflect for $ty<$param> {
+            fn type_name(&self) -> &'static str {
+                std::any::type_name::<Self>()
+            }
+
+            fn fields(&self) -> Vec<FieldInfo> {
+                vec![$(FieldInfo {
+                    name: stringify!($field).to_string(),
+                    type_name: std::any::type_name::<$field_ty>(),
+                }),*]
+            }
+
+            fn field(&self, name: &str) -> Option<&dyn Reflect> {
+                match name {
+                    $(stringify!($field) => Some(&self.$field),)*
+                    _ => None,
+                }
+            }
+
+            fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+                match name {
+                    $(stringify!($field) => Some(&mut self.$field),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_reflect_for_math!(Vector2<S> { x: S, y: S });
+impl_reflect_for_math!(Vector3<S> { x: S, y: S, z: S });
+impl_reflect_for_math!(Vector4<S> { x: S, y: S, z: S, w: S });
+impl_reflect_for_math!(Quaternion<S> { s: S, v: Vector3<S> });
+impl_reflect_for_math!(Matrix4<S> { x: Vector4<S>, y: Vector4<S>, z: Vector4<S>, w: Vector4<S> });
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{component::SerializableComponent, naming::Name};
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent, Reflect)]
+    struct ReflectHealth {
+        current: i32,
+        max: i32,
+    }
+
+    #[test]
+    fn setting_a_path_runs_replace_hooks() {
+        let mut world = World::default();
+        let replaced = Arc::new(Mutex::new(Vec::new()));
+        let hook_replaced = replaced.clone();
+        world
+            .hooks_mut()
+            .on_replace::<ReflectHealth>(move |_, _, old| {
+                hook_replaced.lock().unwrap().push(old.current)
+            });
+
+        let entity_id = world.spawn((ReflectHealth {
+            current: 100,
+            max: 100,
+        },));
+        world
+            .set_reflect_path(entity_id, "ReflectHealth.current", "50")
+            .unwrap();
+
+        assert_eq!(
+            ReflectHealth::query_one(&world, entity_id),
+            Some(&ReflectHealth {
+                current: 50,
+                max: 100
+            })
+        );
+        assert_eq!(*replaced.lock().unwrap(), [100]);
+    }
+
+    #[test]
+    fn setting_a_name_updates_the_name_index() {
+        let mut world = World::default();
+        let entity_id = world.spawn((Name::new("player"),));
+
+        world
+            .set_reflect_path(entity_id, "Name.0", "\"hero\"")
+            .unwrap();
+
+        assert_eq!(world.find_by_name("hero"), Some(entity_id));
+        assert_eq!(world.find_by_name("player"), None);
+    }
+
+    #[test]
+    fn invalid_paths_leave_the_component_unchanged() {
+        let mut world = World::default();
+        let entity_id = world.spawn((ReflectHealth {
+            current: 100,
+            max: 100,
+        },));
+        let since = world.increment_change_tick();
+
+        assert!(
+            world
+                .set_reflect_path(entity_id, "ReflectHealth.armor", "5")
+                .is_err()
+        );
+        assert!(
+            !world
+                .component_ticks(entity_id, ReflectHealth::COMPONENT_ID)
+                .unwrap()
+                .is_changed(since)
+        );
+    }
+}
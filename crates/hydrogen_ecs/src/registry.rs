@@ -0,0 +1,161 @@
+use std::{any::TypeId, collections::BTreeMap, fmt, sync::OnceLock};
+
+use thiserror::Error;
+
+use crate::component::ComponentId;
+
+pub use inventory;
+
+/// Type information about a component, submitted by the `Component` and `SerializableComponent` derives. Generic
+/// components aren't registered.
+#[derive(Debug, Clone, Copy)]
+pub struct ComponentRegistration {
+    pub component_id: ComponentId,
+    pub version: u32,
+    pub display_name: &'static str,
+    /// The full path of the type, including the module it was declared in.
+    pub type_path: &'static str,
+    pub serializable: bool,
+    pub type_id: fn() -> TypeId,
+}
+
+inventory::collect!(ComponentRegistration);
+
+impl ComponentRegistration {
+    pub fn type_id(&self) -> TypeId {
+        (self.type_id)()
+    }
+}
+
+/// Two or more distinct types that share both a [`ComponentId`] and a version.
+#[derive(Debug, Clone)]
+pub struct ComponentIdCollision {
+    pub component_id: ComponentId,
+    pub version: u32,
+    pub registrations: Vec<&'static ComponentRegistration>,
+}
+
+impl fmt::Display for ComponentIdCollision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let type_paths: Vec<&str> = self
+            .registrations
+            .iter()
+            .map(|registration| registration.type_path)
+            .collect();
+
+        write!(
+            f,
+            "{} (version {}) is used by {}",
+            self.component_id.0,
+            self.version,
+            type_paths.join(", ")
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+#[error(
+    "component ID collisions, give the types distinct `#[component(id = \"...\")]` attributes: {}",
+    .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+)]
+pub struct ComponentIdCollisionError(pub Vec<ComponentIdCollision>);
+
+/// Every component type linked into the program, collected at startup.
+///
+/// Several types may share a [`ComponentId`] as long as their versions differ, since older versions are kept around
+/// for [migrations](crate::migration::ComponentMigrations). Anything else is a collision.
+#[derive(Debug)]
+pub struct ComponentRegistry {
+    /// Sorted by version.
+    registrations: BTreeMap<ComponentId, Vec<&'static ComponentRegistration>>,
+    collisions: Vec<ComponentIdCollision>,
+}
+
+impl ComponentRegistry {
+    pub fn global() -> &'static Self {
+        static REGISTRY: OnceLock<ComponentRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(Self::collect)
+    }
+
+    fn collect() -> Self {
+        let mut registrations: BTreeMap<ComponentId, Vec<&'static ComponentRegistration>> =
+            BTreeMap::new();
+        for registration in inventory::iter::<ComponentRegistration> {
+            registrations
+                .entry(registration.component_id)
+                .or_default()
+                .push(registration);
+        }
+
+        let mut collisions = Vec::new();
+        for (&component_id, versions) in registrations.iter_mut() {
+            versions.sort_by_key(|registration| (registration.version, registration.type_path));
+
+            for chunk in versions.chunk_by(|a, b| a.version == b.version) {
+                if chunk.len() > 1 {
+                    collisions.push(ComponentIdCollision {
+                        component_id,
+                        version: chunk[0].version,
+                        registrations: chunk.to_vec(),
+                    });
+                }
+            }
+        }
+
+        Self {
+            registrations,
+            collisions,
+        }
+    }
+
+    /// The newest version of the component.
+    pub fn get(&self, component_id: ComponentId) -> Option<&'static ComponentRegistration> {
+        self.registrations.get(&component_id)?.last().copied()
+    }
+
+    pub fn get_version(
+        &self,
+        component_id: ComponentId,
+        version: u32,
+    ) -> Option<&'static ComponentRegistration> {
+        self.registrations
+            .get(&component_id)?
+            .iter()
+            .find(|registration| registration.version == version)
+            .copied()
+    }
+
+    /// Every registered version of the component, oldest first.
+    pub fn versions(&self, component_id: ComponentId) -> &[&'static ComponentRegistration] {
+        self.registrations
+            .get(&component_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn display_name(&self, component_id: ComponentId) -> Option<&'static str> {
+        self.get(component_id)
+            .map(|registration| registration.display_name)
+    }
+
+    pub fn component_ids(&self) -> impl Iterator<Item = ComponentId> {
+        self.registrations.keys().copied()
+    }
+
+    /// Every registered component type, including old versions, ordered by ID and then version.
+    pub fn iter(&self) -> impl Iterator<Item = &'static ComponentRegistration> {
+        self.registrations.values().flatten().copied()
+    }
+
+    pub fn collisions(&self) -> &[ComponentIdCollision] {
+        &self.collisions
+    }
+
+    pub fn check_collisions(&self) -> Result<(), ComponentIdCollisionError> {
+        if self.collisions.is_empty() {
+            Ok(())
+        } else {
+            Err(ComponentIdCollisionError(self.collisions.clone()))
+        }
+    }
+}
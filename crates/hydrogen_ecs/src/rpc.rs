@@ -0,0 +1,40 @@
+use std::mem;
+
+use crate::{
+    component::SerializableComponent,
+    ecs_net::{NetEcsCommand, ServerEntityId},
+    entity::EntityId,
+    world::World,
+};
+
+/// An RPC waiting to be sent by every server-side [`EcsReplicator`](crate::ecs_net::EcsReplicator).
+#[derive(Debug)]
+pub(crate) struct EntityRpc {
+    pub(crate) server_entity_id: ServerEntityId,
+    pub(crate) rpc: Box<dyn SerializableComponent>,
+}
+
+impl EntityRpc {
+    pub(crate) fn command(&self) -> NetEcsCommand {
+        NetEcsCommand::Rpc(self.server_entity_id, self.rpc.clone_box())
+    }
+}
+
+impl World {
+    /// Sends a one-off message about an entity, such as playing a sound or spawning an effect, to every client that
+    /// has the entity when its [`EcsReplicator`](crate::ecs_net::EcsReplicator) next updates. Clients that don't have
+    /// the entity never get the message, and clients that are sent the entity in that same update get it after the
+    /// entity. A replicator only sends the RPCs sent after its first update, so new clients don't get old messages.
+    pub fn send_rpc(&mut self, entity_id: EntityId, rpc: impl SerializableComponent) {
+        self.net_queues().rpcs.send(EntityRpc {
+            server_entity_id: ServerEntityId(entity_id),
+            rpc: Box::new(rpc),
+        });
+    }
+
+    /// Takes the RPCs received from the server since the last call, in the order they were sent, along with the
+    /// entities they're about.
+    pub fn take_rpcs(&mut self) -> Vec<(EntityId, Box<dyn SerializableComponent>)> {
+        mem::take(&mut self.net_queues().incoming_rpcs)
+    }
+}
@@ -0,0 +1,368 @@
+use std::collections::BTreeMap;
+
+use hydrogen_data_structures::compression::Compressed;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    component::{ComponentId, SerializableComponentBundle},
+    entity::EntityId,
+    migration::MigrationError,
+    world::World,
+};
+
+/// Bumped whenever the layout of [`WorldSnapshot`] changes in a way that breaks older save files.
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+const SNAPSHOT_MAGIC: [u8; 4] = *b"HYWS";
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("data is not a world snapshot")]
+    BadMagic,
+    #[error("unsupported snapshot format version {0}, expected {SNAPSHOT_FORMAT_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("postcard error: {0}")]
+    Postcard(#[from] postcard::Error),
+    #[error("RON serialization error: {0}")]
+    RonSerialize(#[from] ron::Error),
+    #[error("RON deserialization error: {0}")]
+    RonDeserialize(#[from] ron::error::SpannedError),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct SnapshotHeader {
+    magic: [u8; 4],
+    format_version: u32,
+    compressed: bool,
+}
+
+/// A component that was left out of a [`WorldSnapshot`] because it isn't a
+/// [`SerializableComponent`](crate::component::SerializableComponent).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkippedComponent {
+    /// `None` if the component is a resource.
+    pub entity_id: Option<EntityId>,
+    pub component_id: ComponentId,
+    pub display_name: &'static str,
+}
+
+/// Every serializable component and resource in a [`World`], along with the entity IDs they belong to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct WorldSnapshot {
+    pub format_version: u32,
+    pub next_entity_id: u32,
+    pub entities: BTreeMap<EntityId, SerializableComponentBundle>,
+    pub resources: SerializableComponentBundle,
+    /// Components that couldn't be included in the snapshot. This is never saved.
+    #[serde(skip)]
+    pub skipped_components: Vec<SkippedComponent>,
+}
+
+impl WorldSnapshot {
+    fn encode(&self, compressed: bool) -> Result<Vec<u8>, SnapshotError> {
+        let header = SnapshotHeader {
+            magic: SNAPSHOT_MAGIC,
+            format_version: self.format_version,
+            compressed,
+        };
+
+        let mut bytes = postcard::to_allocvec(&header)?;
+        if compressed {
+            bytes.extend(postcard::to_allocvec(&Compressed::new(self)?)?);
+        } else {
+            bytes.extend(postcard::to_allocvec(self)?);
+        }
+
+        Ok(bytes)
+    }
+
+    /// Encodes the snapshot into the versioned binary format.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        self.encode(false)
+    }
+
+    /// Like [`WorldSnapshot::to_bytes`], but the body is compressed.
+    pub fn to_compressed_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        self.encode(true)
+    }
+
+    /// Decodes a snapshot created by either [`WorldSnapshot::to_bytes`] or [`WorldSnapshot::to_compressed_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let (header, body) = postcard::take_from_bytes::<SnapshotHeader>(bytes)
+            .map_err(|_| SnapshotError::BadMagic)?;
+
+        if header.magic != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        if header.format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header.format_version));
+        }
+
+        if header.compressed {
+            Ok(postcard::from_bytes::<Compressed<Self>>(body)?.decompress()?)
+        } else {
+            Ok(postcard::from_bytes(body)?)
+        }
+    }
+
+    /// Encodes the snapshot as human-readable RON, for debugging save files.
+    pub fn to_ron_string(&self) -> Result<String, SnapshotError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    pub fn from_ron_str(ron: &str) -> Result<Self, SnapshotError> {
+        let snapshot: Self = ron::from_str(ron)?;
+        if snapshot.format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(snapshot.format_version));
+        }
+
+        Ok(snapshot)
+    }
+}
+
+impl World {
+    /// Captures every serializable component and resource in the world. Non-serializable ones are listed in
+    /// [`WorldSnapshot::skipped_components`].
+    pub fn snapshot(&self) -> WorldSnapshot {
+        let mut snapshot = WorldSnapshot {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            next_entity_id: self.peek_next_entity_id().0,
+            ..Default::default()
+        };
+
+        for entity_id in self.entities() {
+            let mut bundle = SerializableComponentBundle::new();
+            for (component_id, component) in self.get_all_components(entity_id) {
+                if let Some(serializable_component) = component.as_serializable() {
+                    bundle.set_component_boxed(serializable_component.clone_box());
+                } else {
+                    snapshot.skipped_components.push(SkippedComponent {
+                        entity_id: Some(entity_id),
+                        component_id,
+                        display_name: component.display_name(),
+                    });
+                }
+            }
+
+            if !bundle.is_empty() {
+                snapshot.entities.insert(entity_id, bundle);
+            }
+        }
+
+        for (component_id, resource) in self.resources() {
+            if let Some(serializable_resource) = resource.as_serializable() {
+                snapshot
+                    .resources
+                    .set_component_boxed(serializable_resource.clone_box());
+            } else {
+                snapshot.skipped_components.push(SkippedComponent {
+                    entity_id: None,
+                    component_id,
+                    display_name: resource.display_name(),
+                });
+            }
+        }
+
+        snapshot
+    }
+
+    /// Replaces the entire contents of the world with the contents of `snapshot`, keeping the same entity IDs.
+    /// Components from older versions are upgraded with the world's [`migrations`](World::migrations).
+    ///
+    /// Returns the errors of any components that couldn't be migrated, which are left out of the world.
+    pub fn restore(&mut self, snapshot: WorldSnapshot) -> Vec<MigrationError> {
+        self.clear();
+        self.ensure_next_entity_id(snapshot.next_entity_id);
+
+        let mut errors = Vec::new();
+
+        for (entity_id, bundle) in snapshot.entities {
+            for component in bundle.into_serializable_components() {
+                match self.migrations().migrate(component) {
+                    Ok(component) => {
+                        self.set_component_boxed(entity_id, component);
+                    }
+                    Err(e) => errors.push(e),
+                }
+            }
+        }
+
+        for resource in snapshot.resources.into_serializable_components() {
+            match self.migrations().migrate(resource) {
+                Ok(resource) => {
+                    self.insert_resource_boxed(resource);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::{Component, SerializableComponent};
+
+    mod hydrogen {
+        pub use crate as ecs;
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct SnapshotHealth(u32);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct SnapshotClock(u64);
+
+    #[derive(Debug, Component)]
+    struct SnapshotCache;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    #[component(id = "snapshot_score")]
+    struct OldSnapshotScore(i32);
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    #[component(id = "snapshot_score", version = 1)]
+    struct SnapshotScore(i64);
+
+    fn world() -> (World, EntityId, EntityId) {
+        let mut world = World::default();
+        let player = world.spawn((SnapshotHealth(10), SnapshotCache));
+        let removed = world.spawn((SnapshotHealth(0),));
+        world.delete_entity(removed);
+        let enemy = world.spawn((SnapshotHealth(3),));
+        world.insert_resource(SnapshotClock(42));
+        (world, player, enemy)
+    }
+
+    fn assert_restored(
+        snapshot: WorldSnapshot,
+        player: EntityId,
+        enemy: EntityId,
+        next_entity_id: EntityId,
+    ) {
+        let mut world = World::default();
+        world.spawn((SnapshotHealth(99),));
+        assert!(world.restore(snapshot).is_empty());
+
+        assert_eq!(world.entities().collect::<Vec<_>>(), [player, enemy]);
+        assert_eq!(
+            SnapshotHealth::query_one(&world, player),
+            Some(&SnapshotHealth(10))
+        );
+        assert_eq!(
+            SnapshotHealth::query_one(&world, enemy),
+            Some(&SnapshotHealth(3))
+        );
+        assert!(SnapshotCache::query_one(&world, player).is_none());
+        assert_eq!(
+            world.get_resource::<SnapshotClock>(),
+            Some(&SnapshotClock(42))
+        );
+        assert_eq!(world.peek_next_entity_id(), next_entity_id);
+    }
+
+    #[test]
+    fn snapshots_skip_components_that_arent_serializable() {
+        let (world, player, _) = world();
+        let snapshot = world.snapshot();
+
+        assert_eq!(snapshot.format_version, SNAPSHOT_FORMAT_VERSION);
+        assert_eq!(snapshot.entities.len(), 2);
+        assert_eq!(
+            snapshot.skipped_components,
+            [SkippedComponent {
+                entity_id: Some(player),
+                component_id: SnapshotCache::COMPONENT_ID,
+                display_name: SnapshotCache.display_name(),
+            }]
+        );
+    }
+
+    #[test]
+    fn binary_snapshots_round_trip() {
+        let (world, player, enemy) = world();
+        let snapshot = world.snapshot();
+
+        for bytes in [
+            snapshot.to_bytes().unwrap(),
+            snapshot.to_compressed_bytes().unwrap(),
+        ] {
+            let decoded = WorldSnapshot::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.entities, snapshot.entities);
+            assert_eq!(decoded.resources, snapshot.resources);
+            assert_restored(decoded, player, enemy, world.peek_next_entity_id());
+        }
+    }
+
+    #[test]
+    fn ron_snapshots_round_trip() {
+        let (world, player, enemy) = world();
+        let snapshot = world.snapshot();
+
+        let decoded = WorldSnapshot::from_ron_str(&snapshot.to_ron_string().unwrap()).unwrap();
+        assert_eq!(decoded.entities, snapshot.entities);
+        assert_restored(decoded, player, enemy, world.peek_next_entity_id());
+    }
+
+    #[test]
+    fn other_data_and_formats_are_refused() {
+        assert!(matches!(
+            WorldSnapshot::from_bytes(b"not a snapshot"),
+            Err(SnapshotError::BadMagic)
+        ));
+        assert!(matches!(
+            WorldSnapshot::from_bytes(&[]),
+            Err(SnapshotError::BadMagic)
+        ));
+
+        let mut snapshot = World::default().snapshot();
+        snapshot.format_version = SNAPSHOT_FORMAT_VERSION + 1;
+        assert!(matches!(
+            WorldSnapshot::from_bytes(&snapshot.to_bytes().unwrap()),
+            Err(SnapshotError::UnsupportedVersion(version)) if version == SNAPSHOT_FORMAT_VERSION + 1
+        ));
+        assert!(matches!(
+            WorldSnapshot::from_ron_str(&snapshot.to_ron_string().unwrap()),
+            Err(SnapshotError::UnsupportedVersion(version)) if version == SNAPSHOT_FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn old_components_are_migrated_on_restore() {
+        let mut old_world = World::default();
+        let entity_id = old_world.spawn((OldSnapshotScore(4),));
+        let bytes = old_world.snapshot().to_bytes().unwrap();
+
+        // without a migration, the component is left out
+        let mut world = World::default();
+        let errors = world.restore(WorldSnapshot::from_bytes(&bytes).unwrap());
+        assert!(matches!(
+            errors[..],
+            [MigrationError::MissingMigration {
+                from_version: 0,
+                current_version: 1,
+                ..
+            }]
+        ));
+        assert!(world.get_all_components(entity_id).next().is_none());
+
+        world
+            .migrations_mut()
+            .register(|OldSnapshotScore(score): OldSnapshotScore| SnapshotScore(score as i64 * 2));
+        assert!(
+            world
+                .restore(WorldSnapshot::from_bytes(&bytes).unwrap())
+                .is_empty()
+        );
+        assert_eq!(
+            SnapshotScore::query_one(&world, entity_id),
+            Some(&SnapshotScore(8))
+        );
+    }
+}
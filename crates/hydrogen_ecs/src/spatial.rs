@@ -0,0 +1,491 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use hydrogen_math::bounding_box::{BBox3, Point};
+
+use crate::{
+    change_detection::{QueryFilter, Tick},
+    component::{Component, ComponentId, ComponentType},
+    entity::EntityId,
+    transform::{GlobalTransform, Transform},
+    world::World,
+};
+
+mod hydrogen {
+    pub use crate as ecs;
+}
+
+/// Entities that would cover more grid cells than this are kept in a separate list that every query checks.
+const MAX_CELLS_PER_ENTITY: usize = 64;
+
+/// Implemented by components that give an entity a place in a [`SpatialIndex`].
+pub trait SpatialBounds {
+    /// The volume the entity takes up. Entities that are only a point should return a box with no size, which can be
+    /// found by radius and box queries, but won't be hit by rays.
+    fn spatial_bounds(&self) -> BBox3;
+}
+
+impl SpatialBounds for Transform {
+    fn spatial_bounds(&self) -> BBox3 {
+        BBox3::new([self.translation])
+    }
+}
+
+impl SpatialBounds for GlobalTransform {
+    fn spatial_bounds(&self) -> BBox3 {
+        BBox3::new([self.translation()])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub entity_id: EntityId,
+    /// How far along the ray the entity's bounds were entered, in multiples of the ray direction's length.
+    pub distance: f32,
+}
+
+type Cell = [i32; 3];
+
+/// A uniform grid of the entities that have a particular [`SpatialBounds`] component, for finding entities by
+/// location without checking every one of them.
+///
+/// Usually stored as a world resource and kept up to date with [`World::update_spatial_index`], which only looks at
+/// components that were added or changed since the last update.
+#[derive(Debug, Component)]
+pub struct SpatialIndex {
+    component_id: ComponentId,
+    bounds_of: fn(&dyn Component) -> Option<BBox3>,
+    cell_size: f32,
+    cells: HashMap<Cell, Vec<EntityId>>,
+    oversized: BTreeSet<EntityId>,
+    entities: BTreeMap<EntityId, BBox3>,
+    last_updated: Tick,
+}
+
+impl SpatialIndex {
+    /// Creates an index of every entity with a `T` component. `cell_size` should be around the size of a typical
+    /// query.
+    pub fn new<T: ComponentType + SpatialBounds>(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "cell size must be positive");
+
+        Self {
+            component_id: T::COMPONENT_ID,
+            bounds_of: |component| {
+                component
+                    .downcast_ref::<T>()
+                    .map(SpatialBounds::spatial_bounds)
+            },
+            cell_size,
+            cells: HashMap::new(),
+            oversized: BTreeSet::new(),
+            entities: BTreeMap::new(),
+            last_updated: Tick::default(),
+        }
+    }
+
+    pub fn component_id(&self) -> ComponentId {
+        self.component_id
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn contains(&self, entity_id: EntityId) -> bool {
+        self.entities.contains_key(&entity_id)
+    }
+
+    pub fn bounds(&self, entity_id: EntityId) -> Option<BBox3> {
+        self.entities.get(&entity_id).copied()
+    }
+
+    /// Brings the index up to date with the world, re-inserting every entity whose component was added or changed
+    /// since the last update and removing those that lost it.
+    pub fn update(&mut self, world: &World) {
+        let removed: Vec<EntityId> = self
+            .entities
+            .keys()
+            .copied()
+            .filter(|&entity_id| !world.has_component(entity_id, self.component_id))
+            .collect();
+        for entity_id in removed {
+            self.remove(entity_id);
+        }
+
+        // components can still change during the tick of the last update, after it ran
+        let since = Tick(self.last_updated.0.saturating_sub(1));
+        let changed: Vec<(EntityId, BBox3)> = world
+            .query_filtered(
+                [self.component_id],
+                [],
+                [QueryFilter::Changed(self.component_id)],
+                since,
+            )
+            .filter_map(|(entity_id, [component])| {
+                Some((entity_id, (self.bounds_of)(component.as_ref())?))
+            })
+            .collect();
+        for (entity_id, bounds) in changed {
+            self.insert(entity_id, bounds);
+        }
+
+        self.last_updated = world.change_tick();
+    }
+
+    /// Rebuilds the index from scratch.
+    pub fn rebuild(&mut self, world: &World) {
+        self.clear();
+        self.update(world);
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.oversized.clear();
+        self.entities.clear();
+        self.last_updated = Tick::default();
+    }
+
+    fn insert(&mut self, entity_id: EntityId, bounds: BBox3) {
+        if self.entities.get(&entity_id) == Some(&bounds) {
+            return;
+        }
+        self.remove(entity_id);
+
+        let (min, max) = self.cell_range(bounds);
+        if cell_count(min, max) > MAX_CELLS_PER_ENTITY {
+            self.oversized.insert(entity_id);
+        } else {
+            for cell in cells_in_range(min, max) {
+                self.cells.entry(cell).or_default().push(entity_id);
+            }
+        }
+
+        self.entities.insert(entity_id, bounds);
+    }
+
+    fn remove(&mut self, entity_id: EntityId) {
+        let Some(bounds) = self.entities.remove(&entity_id) else {
+            return;
+        };
+
+        if self.oversized.remove(&entity_id) {
+            return;
+        }
+
+        let (min, max) = self.cell_range(bounds);
+        for cell in cells_in_range(min, max) {
+            if let Some(entity_ids) = self.cells.get_mut(&cell) {
+                entity_ids.retain(|&id| id != entity_id);
+                if entity_ids.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    /// The lowest and highest cell coordinates on each axis that have entities in them.
+    fn occupied_cell_range(&self) -> Option<(Cell, Cell)> {
+        let mut cells = self.cells.keys();
+        let first = *cells.next()?;
+        Some(cells.fold((first, first), |(min, max), cell| {
+            (
+                [0, 1, 2].map(|axis| min[axis].min(cell[axis])),
+                [0, 1, 2].map(|axis| max[axis].max(cell[axis])),
+            )
+        }))
+    }
+
+    fn cell_of(&self, point: Point<3>) -> Cell {
+        point.map(|value| (value / self.cell_size).floor() as i32)
+    }
+
+    /// The lowest and highest cells that `bounds` overlaps.
+    fn cell_range(&self, bounds: BBox3) -> (Cell, Cell) {
+        (
+            self.cell_of(bounds.get_corner([false; 3])),
+            self.cell_of(bounds.get_corner([true; 3])),
+        )
+    }
+
+    /// Every entity that might overlap `bounds`, without checking their actual bounds.
+    fn candidates(&self, bounds: BBox3) -> BTreeSet<EntityId> {
+        let mut candidates = self.oversized.clone();
+
+        let (min, max) = self.cell_range(bounds);
+        if cell_count(min, max) > self.cells.len() {
+            // cheaper to check every entity than every cell
+            candidates.extend(self.entities.keys());
+        } else {
+            for cell in cells_in_range(min, max) {
+                if let Some(entity_ids) = self.cells.get(&cell) {
+                    candidates.extend(entity_ids);
+                }
+            }
+        }
+
+        candidates
+    }
+
+    /// Every entity whose bounds are within `radius` of `center`.
+    pub fn within_radius(&self, center: Point<3>, radius: f32) -> Vec<EntityId> {
+        let query_bounds = BBox3::new([center.map(|v| v - radius), center.map(|v| v + radius)]);
+
+        self.candidates(query_bounds)
+            .into_iter()
+            .filter(|entity_id| {
+                distance_squared_to_box(center, self.entities[entity_id]) <= radius * radius
+            })
+            .collect()
+    }
+
+    /// Every entity whose bounds overlap `bounds`, including those that only touch its edges.
+    pub fn within_box(&self, bounds: BBox3) -> Vec<EntityId> {
+        self.candidates(bounds)
+            .into_iter()
+            .filter(|entity_id| boxes_overlap(bounds, self.entities[entity_id]))
+            .collect()
+    }
+
+    /// Every entity whose bounds are hit by a ray from `origin` towards `direction`, within `max_distance` multiples
+    /// of `direction`, which may be infinite. Hits are sorted from nearest to farthest.
+    ///
+    /// A zero or NaN `direction` hits nothing.
+    pub fn raycast(&self, origin: Point<3>, direction: Point<3>, max_distance: f32) -> Vec<RayHit> {
+        if direction.iter().any(|value| value.is_nan())
+            || direction.iter().all(|&value| value == 0.0)
+            || max_distance.is_nan()
+        {
+            return Vec::new();
+        }
+
+        let mut candidates = self.oversized.clone();
+        for cell in self.cells_along_ray(origin, direction, max_distance) {
+            if let Some(entity_ids) = self.cells.get(&cell) {
+                candidates.extend(entity_ids);
+            }
+        }
+
+        let mut hits: Vec<RayHit> = candidates
+            .into_iter()
+            .filter_map(|entity_id| {
+                let distance = ray_box_distance(origin, direction, self.entities[&entity_id])?;
+                (distance <= max_distance).then_some(RayHit {
+                    entity_id,
+                    distance,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+
+    /// Walks the grid cells that a ray passes through, in order, stopping once there are no occupied cells left ahead.
+    fn cells_along_ray(
+        &self,
+        origin: Point<3>,
+        direction: Point<3>,
+        max_distance: f32,
+    ) -> Vec<Cell> {
+        let Some((occupied_min, occupied_max)) = self.occupied_cell_range() else {
+            return Vec::new();
+        };
+
+        let mut cell = self.cell_of(origin);
+        let mut cells = vec![cell];
+
+        let mut step = [0; 3];
+        let mut next_boundary = [f32::INFINITY; 3];
+        let mut boundary_spacing = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                let boundary = (cell[axis] + 1) as f32 * self.cell_size;
+                next_boundary[axis] = (boundary - origin[axis]) / direction[axis];
+                boundary_spacing[axis] = self.cell_size / direction[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                let boundary = cell[axis] as f32 * self.cell_size;
+                next_boundary[axis] = (boundary - origin[axis]) / direction[axis];
+                boundary_spacing[axis] = -self.cell_size / direction[axis];
+            }
+        }
+
+        loop {
+            let axis = (0..3)
+                .min_by(|&a, &b| next_boundary[a].total_cmp(&next_boundary[b]))
+                .unwrap();
+            if next_boundary[axis].is_infinite() || next_boundary[axis] > max_distance {
+                break;
+            }
+
+            // past the last occupied cell along an axis, every cell further on is empty
+            let passed_occupied = (step[axis] > 0 && cell[axis] >= occupied_max[axis])
+                || (step[axis] < 0 && cell[axis] <= occupied_min[axis]);
+            if passed_occupied {
+                break;
+            }
+
+            cell[axis] += step[axis];
+            next_boundary[axis] += boundary_spacing[axis];
+            cells.push(cell);
+        }
+
+        cells
+    }
+}
+
+fn cell_count(min: Cell, max: Cell) -> usize {
+    (0..3)
+        .map(|axis| (max[axis] as i64 - min[axis] as i64 + 1) as usize)
+        .fold(1, usize::saturating_mul)
+}
+
+fn cells_in_range(min: Cell, max: Cell) -> impl Iterator<Item = Cell> {
+    (min[0]..=max[0]).flat_map(move |x| {
+        (min[1]..=max[1]).flat_map(move |y| (min[2]..=max[2]).map(move |z| [x, y, z]))
+    })
+}
+
+fn distance_squared_to_box(point: Point<3>, bounds: BBox3) -> f32 {
+    let min = bounds.get_corner([false; 3]);
+    let max = bounds.get_corner([true; 3]);
+
+    (0..3)
+        .map(|axis| {
+            let closest = point[axis].clamp(min[axis], max[axis]);
+            (point[axis] - closest).powi(2)
+        })
+        .sum()
+}
+
+fn boxes_overlap(a: BBox3, b: BBox3) -> bool {
+    let (a_min, a_max) = (a.get_corner([false; 3]), a.get_corner([true; 3]));
+    let (b_min, b_max) = (b.get_corner([false; 3]), b.get_corner([true; 3]));
+
+    (0..3).all(|axis| a_min[axis] <= b_max[axis] && b_min[axis] <= a_max[axis])
+}
+
+/// The distance along the ray at which it enters `bounds`, or 0 if it starts inside.
+fn ray_box_distance(origin: Point<3>, direction: Point<3>, bounds: BBox3) -> Option<f32> {
+    let min = bounds.get_corner([false; 3]);
+    let max = bounds.get_corner([true; 3]);
+
+    let mut enter = 0.0f32;
+    let mut exit = f32::INFINITY;
+    for axis in 0..3 {
+        if direction[axis] == 0.0 {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let a = (min[axis] - origin[axis]) / direction[axis];
+        let b = (max[axis] - origin[axis]) / direction[axis];
+        enter = enter.max(a.min(b));
+        exit = exit.min(a.max(b));
+    }
+
+    (enter <= exit).then_some(enter)
+}
+
+impl World {
+    pub fn spatial_index(&self) -> Option<&SpatialIndex> {
+        self.get_resource::<SpatialIndex>()
+    }
+
+    /// Updates the [`SpatialIndex`] resource, if there is one. Call this once per frame, after moving entities and
+    /// before making spatial queries.
+    pub fn update_spatial_index(&mut self) {
+        self.resource_scope(|world, index: &mut SpatialIndex| index.update(world));
+    }
+
+    /// See [`SpatialIndex::within_radius`]. Returns nothing if there's no [`SpatialIndex`] resource.
+    pub fn entities_within_radius(&self, center: Point<3>, radius: f32) -> Vec<EntityId> {
+        self.spatial_index()
+            .map(|index| index.within_radius(center, radius))
+            .unwrap_or_default()
+    }
+
+    /// See [`SpatialIndex::within_box`]. Returns nothing if there's no [`SpatialIndex`] resource.
+    pub fn entities_within_box(&self, bounds: BBox3) -> Vec<EntityId> {
+        self.spatial_index()
+            .map(|index| index.within_box(bounds))
+            .unwrap_or_default()
+    }
+
+    /// See [`SpatialIndex::raycast`]. Returns nothing if there's no [`SpatialIndex`] resource.
+    pub fn raycast(&self, origin: Point<3>, direction: Point<3>, max_distance: f32) -> Vec<RayHit> {
+        self.spatial_index()
+            .map(|index| index.raycast(origin, direction, max_distance))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Component)]
+    struct SpatialCrate {
+        min: Point<3>,
+        max: Point<3>,
+    }
+
+    impl SpatialBounds for SpatialCrate {
+        fn spatial_bounds(&self) -> BBox3 {
+            BBox3::new([self.min, self.max])
+        }
+    }
+
+    fn world_with_crates(crates: &[(Point<3>, Point<3>)]) -> (World, Vec<EntityId>) {
+        let mut world = World::default();
+        world.insert_resource(SpatialIndex::new::<SpatialCrate>(1.0));
+        let entity_ids = crates
+            .iter()
+            .map(|&(min, max)| world.spawn((SpatialCrate { min, max },)))
+            .collect();
+        world.update_spatial_index();
+        (world, entity_ids)
+    }
+
+    #[test]
+    fn infinite_rays_stop_after_the_last_occupied_cell() {
+        let (world, entity_ids) = world_with_crates(&[
+            ([5.0, 0.0, 0.0], [6.0, 1.0, 1.0]),
+            ([20.0, 0.0, 0.0], [21.0, 1.0, 1.0]),
+        ]);
+
+        let hits = world.raycast([0.5, 0.5, 0.5], [1.0, 0.0, 0.0], f32::INFINITY);
+        assert_eq!(
+            hits.iter().map(|hit| hit.entity_id).collect::<Vec<_>>(),
+            entity_ids
+        );
+        assert_eq!(hits[0].distance, 4.5);
+
+        assert!(
+            world
+                .raycast([0.5, 0.5, 0.5], [-1.0, 0.0, 0.0], f32::INFINITY)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn degenerate_directions_hit_nothing() {
+        let (world, _) = world_with_crates(&[([0.0, 0.0, 0.0], [1.0, 1.0, 1.0])]);
+
+        assert!(world.raycast([0.5, 0.5, 0.5], [0.0; 3], 10.0).is_empty());
+        assert!(
+            world
+                .raycast([0.5, 0.5, 0.5], [f32::NAN, 1.0, 0.0], f32::INFINITY)
+                .is_empty()
+        );
+    }
+}
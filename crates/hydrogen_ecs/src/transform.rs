@@ -0,0 +1,97 @@
+use cgmath::{Matrix4, One, Quaternion, SquareMatrix, Vector3, Zero, vec3};
+
+use crate::{
+    component::Component, entity::EntityId, hierarchy::Parent, query, reflect::Reflect,
+    world::World,
+};
+
+mod hydrogen {
+    pub use crate as ecs;
+}
+
+/// An entity's position, rotation and scale relative to its parent, or to the world if it has no parent.
+#[derive(Debug, Clone, Copy, PartialEq, Component, Reflect)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::zero(),
+            rotation: Quaternion::one(),
+            scale: vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// An entity's transform relative to the world, computed by [`World::propagate_transforms`]. Don't set this manually.
+#[derive(Debug, Clone, Copy, PartialEq, Component, Reflect)]
+pub struct GlobalTransform(pub Matrix4<f32>);
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        Self(Matrix4::identity())
+    }
+}
+
+impl GlobalTransform {
+    pub fn translation(&self) -> Vector3<f32> {
+        self.0.w.truncate()
+    }
+}
+
+impl World {
+    /// Computes the [`GlobalTransform`] of every entity with a [`Transform`] by combining it with the transforms of
+    /// its ancestors. An entity whose parent has no [`Transform`] is treated as a root.
+    pub fn propagate_transforms(&mut self) {
+        let roots: Vec<EntityId> = query!(self, Transform)
+            .filter(|&(entity_id, _)| {
+                !Parent::query_one(self, entity_id)
+                    .is_some_and(|parent| Transform::query_one(self, parent.0).is_some())
+            })
+            .map(|(entity_id, _)| entity_id)
+            .collect();
+
+        let mut stack: Vec<(EntityId, Matrix4<f32>)> = roots
+            .into_iter()
+            .map(|entity_id| (entity_id, Matrix4::identity()))
+            .collect();
+
+        while let Some((entity_id, parent_matrix)) = stack.pop() {
+            let Some(transform) = Transform::query_one(self, entity_id) else {
+                continue;
+            };
+            let global_matrix = parent_matrix * transform.matrix();
+
+            stack.extend(
+                self.children(entity_id)
+                    .iter()
+                    .map(|&child| (child, global_matrix)),
+            );
+
+            if let Some(global_transform) = GlobalTransform::query_one_mut(self, entity_id) {
+                global_transform.0 = global_matrix;
+            } else {
+                self.set_component(entity_id, GlobalTransform(global_matrix));
+            }
+        }
+    }
+}
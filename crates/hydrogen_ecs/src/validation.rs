@@ -0,0 +1,392 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use hydrogen_core::events::{EventReceiver, EventSender};
+use hydrogen_net::server_client::ClientId;
+
+use crate::{
+    component::{ComponentId, ComponentType, SerializableComponent},
+    ecs_net::{Replicate, ServerEntityId},
+    entity::EntityId,
+    query_one,
+    world::World,
+};
+
+mod hydrogen {
+    pub use crate as ecs;
+}
+
+/// What to do with a value a client wrote to a component, decided by a validator registered with
+/// [`ClientWriteValidation::validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteVerdict<T> {
+    Accept,
+    /// Keeps the server's value and sends it back to the client.
+    Reject,
+    /// Sets this value instead and sends it back to the client.
+    Clamp(T),
+}
+
+pub type ValidateWriteFn = Arc<
+    dyn Fn(
+            &World,
+            ClientId,
+            EntityId,
+            &dyn SerializableComponent,
+        ) -> WriteVerdict<Box<dyn SerializableComponent>>
+        + Send
+        + Sync,
+>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteRejection {
+    /// The entity isn't replicated.
+    NotReplicated,
+    /// The component is a [`Replicate`], which clients can never write to.
+    Protected,
+    NotOwner,
+    /// The entity was transferred to the client, which hasn't accepted it yet. See [`World::transfer_ownership`].
+    OwnershipPending,
+    /// The component isn't in [`Replicate::client_writable`].
+    NotWritable,
+    /// The client wrote to the component faster than [`ClientWriteValidation::rate_limit`] allows.
+    RateLimited,
+    /// The validator returned [`WriteVerdict::Reject`].
+    Invalid,
+    /// The validator returned [`WriteVerdict::Clamp`], so a different value was set.
+    Clamped,
+}
+
+/// A client write that wasn't applied as it was, received with [`World::rejected_client_writes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejectedWrite {
+    pub client_id: ClientId,
+    pub server_entity_id: ServerEntityId,
+    pub component_id: ComponentId,
+    pub reason: WriteRejection,
+}
+
+/// Checks the components clients write with [`World::execute_client_net_command`] before they're applied.
+#[derive(Default)]
+pub struct ClientWriteValidation {
+    validators: BTreeMap<ComponentId, ValidateWriteFn>,
+    /// Writes per second allowed per client, entity and component.
+    max_rates: BTreeMap<ComponentId, f32>,
+    last_writes: BTreeMap<ClientId, BTreeMap<(ServerEntityId, ComponentId), Instant>>,
+    rejected_writes: EventSender<RejectedWrite>,
+}
+
+impl fmt::Debug for ClientWriteValidation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientWriteValidation")
+            .field(
+                "validators",
+                &format!("({} component types)", self.validators.len()),
+            )
+            .field("max_rates", &self.max_rates)
+            .field("last_writes", &self.last_writes)
+            .field("rejected_writes", &self.rejected_writes)
+            .finish()
+    }
+}
+
+impl ClientWriteValidation {
+    /// Decides what to do with every `T` a client writes to an entity it owns. Replaces any previous validator for
+    /// `T`.
+    pub fn validate<T: ComponentType + SerializableComponent>(
+        &mut self,
+        validator: impl Fn(&World, ClientId, EntityId, &T) -> WriteVerdict<T> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.validators.insert(
+            T::COMPONENT_ID,
+            Arc::new(move |world, client_id, entity_id, component| {
+                let Some(component) = component.downcast_ref::<T>() else {
+                    return WriteVerdict::Reject;
+                };
+
+                match validator(world, client_id, entity_id, component) {
+                    WriteVerdict::Accept => WriteVerdict::Accept,
+                    WriteVerdict::Reject => WriteVerdict::Reject,
+                    WriteVerdict::Clamp(clamped) => WriteVerdict::Clamp(Box::new(clamped)),
+                }
+            }),
+        );
+        self
+    }
+
+    /// Rejects writes to a `T` that come less than `1 / max_rate` seconds after the last one from the same client to
+    /// the same entity that wasn't itself rate limited.
+    pub fn rate_limit<T: ComponentType>(&mut self, max_rate: f32) -> &mut Self {
+        self.max_rates.insert(T::COMPONENT_ID, max_rate);
+        self
+    }
+
+    pub fn clear(&mut self, component_id: ComponentId) {
+        self.validators.remove(&component_id);
+        self.max_rates.remove(&component_id);
+    }
+
+    fn min_interval(&self, component_id: ComponentId) -> Option<Duration> {
+        let max_rate = self.max_rates.get(&component_id)?;
+        Some(Duration::from_secs_f32(1.0 / max_rate))
+    }
+
+    /// Whether a write is too soon after the last one, remembering it if it isn't.
+    fn is_rate_limited(
+        &mut self,
+        now: Instant,
+        client_id: ClientId,
+        server_entity_id: ServerEntityId,
+        component_id: ComponentId,
+    ) -> bool {
+        let Some(min_interval) = self.min_interval(component_id) else {
+            return false;
+        };
+
+        let last_writes = self.last_writes.entry(client_id).or_default();
+        let key = (server_entity_id, component_id);
+        if let Some(&last_write) = last_writes.get(&key)
+            && now.duration_since(last_write) < min_interval
+        {
+            return true;
+        }
+
+        last_writes.insert(key, now);
+        false
+    }
+
+    /// Forgets a client's writes that no longer limit anything. Called once per update by the client's replicator.
+    pub(crate) fn prune(&mut self, client_id: ClientId, now: Instant) {
+        let Some(mut last_writes) = self.last_writes.remove(&client_id) else {
+            return;
+        };
+
+        last_writes.retain(|&(_, component_id), &mut last_write| {
+            self.min_interval(component_id)
+                .is_some_and(|min_interval| now.duration_since(last_write) < min_interval)
+        });
+        if !last_writes.is_empty() {
+            self.last_writes.insert(client_id, last_writes);
+        }
+    }
+
+    fn forget_client(&mut self, client_id: ClientId) {
+        self.last_writes.remove(&client_id);
+    }
+}
+
+impl World {
+    /// Drops everything waiting for a client's replicator, along with the client's rate limits. Call this when a
+    /// client disconnects, since nothing takes from its queues once its replicator is gone.
+    pub fn forget_client(&mut self, client_id: ClientId) {
+        self.net_queues().incoming_requests.remove(&client_id);
+        self.net_queues().client_writes.remove(&client_id);
+        self.client_write_validation_mut().forget_client(client_id);
+    }
+
+    /// Receives every client write that isn't applied as it was, from when this is called.
+    pub fn rejected_client_writes(&self) -> EventReceiver<RejectedWrite> {
+        self.client_write_validation().rejected_writes.subscribe()
+    }
+
+    /// Applies a component written by a client if it's allowed to, and the value passes validation.
+    ///
+    /// The client is sent the server's value again when its write is rejected or clamped, so that it doesn't keep
+    /// showing its own.
+    pub(crate) fn apply_client_write(
+        &mut self,
+        client_id: ClientId,
+        server_entity_id: ServerEntityId,
+        component: Box<dyn SerializableComponent>,
+    ) {
+        let entity_id = server_entity_id.0;
+        let component_id = component.component_id();
+
+        let rejection = match query_one!(self, entity_id, Replicate) {
+            None => Some(WriteRejection::NotReplicated),
+            Some(_) if component_id == Replicate::COMPONENT_ID => Some(WriteRejection::Protected),
+            Some((replicate,)) if replicate.owner != Some(client_id) => {
+                Some(WriteRejection::NotOwner)
+            }
+            Some(_) if !self.has_authority(client_id, entity_id) => {
+                Some(WriteRejection::OwnershipPending)
+            }
+            Some((replicate,)) if !replicate.client_writable.contains(&component_id) => {
+                Some(WriteRejection::NotWritable)
+            }
+            Some(_) => None,
+        };
+
+        let rejection = rejection.or_else(|| {
+            self.client_write_validation_mut()
+                .is_rate_limited(Instant::now(), client_id, server_entity_id, component_id)
+                .then_some(WriteRejection::RateLimited)
+        });
+
+        let verdict = match rejection {
+            Some(_) => WriteVerdict::Reject,
+            None => match self
+                .client_write_validation()
+                .validators
+                .get(&component_id)
+                .cloned()
+            {
+                Some(validator) => validator(self, client_id, entity_id, component.as_ref()),
+                None => WriteVerdict::Accept,
+            },
+        };
+
+        let (reason, corrected) = match verdict {
+            WriteVerdict::Accept => {
+                self.net_queues()
+                    .client_writes
+                    .entry(client_id)
+                    .or_default()
+                    .push((server_entity_id, component.clone_box()));
+                self.set_component_boxed(entity_id, component);
+                return;
+            }
+            WriteVerdict::Reject => (rejection.unwrap_or(WriteRejection::Invalid), None),
+            WriteVerdict::Clamp(clamped) => (WriteRejection::Clamped, Some(clamped)),
+        };
+
+        if let Some(corrected) = corrected {
+            self.set_component_boxed(entity_id, corrected);
+        }
+
+        // asking for the component on the client's behalf makes its replicator send the server's value in full
+        self.net_queues()
+            .incoming_requests
+            .entry(client_id)
+            .or_default()
+            .push((server_entity_id, component_id));
+        self.client_write_validation()
+            .rejected_writes
+            .send(RejectedWrite {
+                client_id,
+                server_entity_id,
+                component_id,
+                reason,
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hydrogen_data_structures::selection::Selection;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SerializableComponent)]
+    struct ValidatedPosition(f32);
+
+    fn owned_entity(world: &mut World, owner: ClientId) -> ServerEntityId {
+        let entity_id = world.new_entity_id();
+        let server_entity_id = ServerEntityId(entity_id);
+        world.set_component(
+            entity_id,
+            Replicate {
+                server_entity_id,
+                owner: Some(owner),
+                replicate_to: Selection::all(),
+                client_writable: Selection::all(),
+                replicated_components: Selection::all(),
+                auto_replicate_changes: Selection::all(),
+            },
+        );
+        world.set_component(entity_id, ValidatedPosition(0.0));
+        server_entity_id
+    }
+
+    #[test]
+    fn writes_faster_than_the_rate_limit_are_rejected() {
+        let mut world = World::default();
+        world
+            .client_write_validation_mut()
+            .rate_limit::<ValidatedPosition>(10.0);
+        let client_id = ClientId(1);
+        let server_entity_id = owned_entity(&mut world, client_id);
+        let rejected = world.rejected_client_writes();
+
+        world.apply_client_write(
+            client_id,
+            server_entity_id,
+            Box::new(ValidatedPosition(1.0)),
+        );
+        world.apply_client_write(
+            client_id,
+            server_entity_id,
+            Box::new(ValidatedPosition(2.0)),
+        );
+
+        assert_eq!(
+            ValidatedPosition::query_one(&world, server_entity_id.0),
+            Some(&ValidatedPosition(1.0))
+        );
+        assert_eq!(
+            rejected.recv_all(),
+            [Arc::new(RejectedWrite {
+                client_id,
+                server_entity_id,
+                component_id: ValidatedPosition::COMPONENT_ID,
+                reason: WriteRejection::RateLimited,
+            })]
+        );
+
+        // pruning keeps writes that still limit, and forgets the ones that don't
+        let now = Instant::now();
+        world.client_write_validation_mut().prune(client_id, now);
+        assert!(
+            world
+                .client_write_validation()
+                .last_writes
+                .contains_key(&client_id)
+        );
+        world
+            .client_write_validation_mut()
+            .prune(client_id, now + Duration::from_secs(1));
+        assert!(world.client_write_validation().last_writes.is_empty());
+    }
+
+    #[test]
+    fn forgotten_clients_leave_nothing_queued() {
+        let mut world = World::default();
+        world
+            .client_write_validation_mut()
+            .rate_limit::<ValidatedPosition>(10.0);
+        let (client_id, other_client_id) = (ClientId(1), ClientId(2));
+        let server_entity_id = owned_entity(&mut world, client_id);
+        let other_server_entity_id = owned_entity(&mut world, other_client_id);
+
+        world.apply_client_write(
+            client_id,
+            server_entity_id,
+            Box::new(ValidatedPosition(1.0)),
+        );
+        world.apply_client_write(
+            client_id,
+            server_entity_id,
+            Box::new(ValidatedPosition(2.0)),
+        );
+        world.apply_client_write(
+            other_client_id,
+            other_server_entity_id,
+            Box::new(ValidatedPosition(3.0)),
+        );
+
+        world.forget_client(client_id);
+
+        let net_queues = world.net_queues();
+        assert!(!net_queues.client_writes.contains_key(&client_id));
+        assert!(!net_queues.incoming_requests.contains_key(&client_id));
+        assert!(net_queues.client_writes.contains_key(&other_client_id));
+        let last_writes = &world.client_write_validation().last_writes;
+        assert!(!last_writes.contains_key(&client_id));
+        assert!(last_writes.contains_key(&other_client_id));
+    }
+}
@@ -1,25 +1,94 @@
-use std::{any::Any, array, collections::BTreeMap};
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+};
 
-use hydrogen_core::events::EventSender;
+use hydrogen_core::events::{EventReceiver, EventSender};
 use hydrogen_net::server_client::ClientId;
 
 use crate::{
-    change_tracker::{ComponentTrackerEvent, GlobalComponentTracker},
-    component::{Component, ComponentId, ComponentSet, SerializableComponent},
-    ecs_net::{NetEcsCommand, Replicate, ServerEntityId},
+    change_detection::{ComponentTicks, QueryFilter, Tick},
+    change_tracker::{ComponentChange, ComponentTrackerEvent, GlobalComponentTracker},
+    commands::Commands,
+    component::{
+        Bundle, Component, ComponentBundle, ComponentId, ComponentSet, ComponentStorage,
+        ComponentType, SerializableComponent, SerializableComponentBundle, assert_distinct,
+    },
+    ecs_net::{NetEcsCommand, NetQueues, ServerEntityId},
     entity::EntityId,
+    hierarchy::Parent,
+    lifecycle::WorldHooks,
+    migration::{ComponentMigrations, MigrationError},
+    naming::NameIndex,
+    registry::{ComponentIdCollisionError, ComponentRegistry},
+    validation::ClientWriteValidation,
 };
 
-mod hydrogen {
-    pub use crate as ecs;
-}
-
-#[derive(Debug, Default)]
 pub struct World {
     components: BTreeMap<ComponentId, ComponentSet>,
+    resources: BTreeMap<ComponentId, Box<dyn Component>>,
     server_entity_id_map: BTreeMap<ServerEntityId, EntityId>,
-    next_entity_id: u32,
+    next_entity_id: Arc<AtomicU32>,
+    change_tick: Tick,
     change_tracker: GlobalComponentTracker,
+    hooks: WorldHooks,
+    migrations: ComponentMigrations,
+    name_index: NameIndex,
+    net_queues: NetQueues,
+    client_write_validation: ClientWriteValidation,
+}
+
+impl fmt::Debug for World {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entities: BTreeMap<EntityId, Vec<&Box<dyn Component>>> = self
+            .entities()
+            .map(|entity_id| {
+                let components = self
+                    .get_all_components(entity_id)
+                    .map(|(_, component)| component)
+                    .collect();
+                (entity_id, components)
+            })
+            .collect();
+
+        f.debug_struct("World")
+            .field("entities", &self.named(&entities))
+            .field("resources", &self.resources.values().collect::<Vec<_>>())
+            .field("server_entity_id_map", &self.server_entity_id_map)
+            .field("next_entity_id", &self.next_entity_id)
+            .field("change_tick", &self.change_tick)
+            .field("change_tracker", &self.change_tracker)
+            .field("hooks", &self.hooks)
+            .field("migrations", &self.migrations)
+            .field("net_queues", &self.net_queues)
+            .field("client_write_validation", &self.client_write_validation)
+            .finish()
+    }
+}
+
+impl Default for World {
+    /// Doesn't check the component registry, see [`World::try_new`].
+    fn default() -> Self {
+        Self {
+            components: Default::default(),
+            resources: Default::default(),
+            server_entity_id_map: Default::default(),
+            next_entity_id: Default::default(),
+            // start at 1 so that everything counts as changed since `Tick::default()`
+            change_tick: Tick(1),
+            change_tracker: Default::default(),
+            hooks: Default::default(),
+            migrations: Default::default(),
+            name_index: Default::default(),
+            net_queues: Default::default(),
+            client_write_validation: Default::default(),
+        }
+    }
 }
 
 impl World {
@@ -27,9 +96,162 @@ impl World {
         Self::default()
     }
 
+    /// Like [`World::new`], but fails if two registered component types share a [`ComponentId`].
+    pub fn try_new() -> Result<Self, ComponentIdCollisionError> {
+        Self::validate_registry()?;
+        Ok(Self::default())
+    }
+
+    /// Checks that no two registered component types share a [`ComponentId`], which would make them mistaken for each
+    /// other.
+    pub fn validate_registry() -> Result<(), ComponentIdCollisionError> {
+        ComponentRegistry::global().check_collisions()
+    }
+
+    pub fn hooks(&self) -> &WorldHooks {
+        &self.hooks
+    }
+
+    pub fn hooks_mut(&mut self) -> &mut WorldHooks {
+        &mut self.hooks
+    }
+
+    pub fn migrations(&self) -> &ComponentMigrations {
+        &self.migrations
+    }
+
+    pub fn migrations_mut(&mut self) -> &mut ComponentMigrations {
+        &mut self.migrations
+    }
+
+    pub fn client_write_validation(&self) -> &ClientWriteValidation {
+        &self.client_write_validation
+    }
+
+    pub fn client_write_validation_mut(&mut self) -> &mut ClientWriteValidation {
+        &mut self.client_write_validation
+    }
+
+    pub(crate) fn name_index(&self) -> &NameIndex {
+        &self.name_index
+    }
+
+    /// The tick that inserted and mutably accessed components are currently stamped with.
+    pub fn change_tick(&self) -> Tick {
+        self.change_tick
+    }
+
+    /// Advances the world's change tick, returning the tick that just ended. Save the result and pass it as the
+    /// `since` argument of a filtered query to only see changes made after this call.
+    pub fn increment_change_tick(&mut self) -> Tick {
+        let ended_tick = self.change_tick;
+        self.change_tick.0 += 1;
+        ended_tick
+    }
+
+    pub fn component_ticks(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) -> Option<ComponentTicks> {
+        self.components.get(&component_id)?.get_ticks(entity_id)
+    }
+
+    /// The newest tick that any component of the type was added or changed at, or `None` if there have never been any.
+    pub(crate) fn component_set_last_changed(&self, component_id: ComponentId) -> Option<Tick> {
+        Some(self.components.get(&component_id)?.last_changed())
+    }
+
+    /// Marks an entity's component as changed without accessing it. Returns `false` if the entity doesn't have the
+    /// component.
+    pub fn mark_changed(&mut self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        let change_tick = self.change_tick;
+        self.components
+            .get_mut(&component_id)
+            .is_some_and(|component_set| component_set.set_changed(entity_id, change_tick))
+    }
+
     pub fn new_entity_id(&mut self) -> EntityId {
-        self.next_entity_id += 1;
-        (self.next_entity_id - 1).into()
+        self.reserve_entity_id()
+    }
+
+    /// Reserves a fresh [`EntityId`] without requiring mutable access to the world.
+    pub fn reserve_entity_id(&self) -> EntityId {
+        self.next_entity_id.fetch_add(1, Ordering::Relaxed).into()
+    }
+
+    /// The [`EntityId`] that the next call to [`World::new_entity_id`] will return.
+    pub fn peek_next_entity_id(&self) -> EntityId {
+        self.next_entity_id.load(Ordering::Relaxed).into()
+    }
+
+    /// Makes sure that newly created entity IDs start at or above `next_entity_id`.
+    pub fn ensure_next_entity_id(&mut self, next_entity_id: u32) {
+        self.next_entity_id
+            .fetch_max(next_entity_id, Ordering::Relaxed);
+    }
+
+    /// Creates an empty [`Commands`] buffer for this world.
+    pub fn commands(&self) -> Commands {
+        Commands::new(Arc::clone(&self.next_entity_id))
+    }
+
+    pub(crate) fn shares_entity_id_counter(&self, counter: &Arc<AtomicU32>) -> bool {
+        Arc::ptr_eq(&self.next_entity_id, counter)
+    }
+
+    /// Applies and clears every command recorded in `commands`.
+    pub fn apply_commands(&mut self, commands: &mut Commands) {
+        commands.apply(self);
+    }
+
+    /// Creates a new entity out of every component in `bundle`.
+    pub fn spawn(&mut self, bundle: impl Bundle) -> EntityId {
+        let entity_id = self.new_entity_id();
+        self.insert_bundle(entity_id, bundle);
+        entity_id
+    }
+
+    /// Starts building a new entity one component at a time.
+    pub fn build_entity(&mut self) -> EntityBuilder<'_> {
+        let entity_id = self.new_entity_id();
+        EntityBuilder {
+            world: self,
+            entity_id,
+        }
+    }
+
+    /// Sets every component in `bundle` on the given entity, replacing any existing components of the same types.
+    pub fn insert_bundle(&mut self, entity_id: EntityId, bundle: impl Bundle) {
+        for component in bundle.into_components() {
+            self.set_component_boxed(entity_id, component);
+        }
+    }
+
+    /// Removes every component from the given entity, returning them as a [`ComponentBundle`]. Runs the same hooks as
+    /// [`World::delete_entity`].
+    pub fn take_bundle(&mut self, entity_id: EntityId) -> ComponentBundle {
+        self.detach_from_hierarchy(entity_id);
+
+        let removed_components: Vec<Box<dyn Component>> = self
+            .components
+            .values_mut()
+            .filter_map(|component_set| component_set.delete(entity_id))
+            .collect();
+
+        let mut bundle = ComponentBundle::new();
+        for component in removed_components {
+            self.name_index
+                .on_component_deleted(entity_id, component.as_ref());
+            self.change_tracker
+                .on_component_deleted(entity_id, component.as_ref());
+            self.run_remove_hooks(entity_id, component.as_ref());
+            bundle.set_component_boxed(component);
+        }
+        if !bundle.is_empty() {
+            self.run_despawn_hooks(entity_id);
+        }
+        bundle
     }
 
     pub fn entity_id_from_server(&mut self, server_entity_id: ServerEntityId) -> EntityId {
@@ -43,47 +265,181 @@ impl World {
         }
     }
 
-    pub fn execute_net_command(&mut self, command: NetEcsCommand) {
-        let entity_id = self.entity_id_from_server(command.server_entity_id());
+    /// Like [`World::entity_id_from_server`], but returns `None` instead of creating a new entity ID.
+    pub fn try_entity_id_from_server(&self, server_entity_id: ServerEntityId) -> Option<EntityId> {
+        self.server_entity_id_map.get(&server_entity_id).copied()
+    }
+
+    /// Whether any server entity IDs have been mapped to entities in this world, i.e. whether it's a client world.
+    pub fn has_server_entity_ids(&self) -> bool {
+        !self.server_entity_id_map.is_empty()
+    }
+
+    /// Applies a command sent by the server. Fails if a component in the command couldn't be migrated to its current
+    /// version, in which case the command is dropped.
+    ///
+    /// A delta that doesn't match the world's value of its component is dropped as well, and the whole component is
+    /// requested from the server the next time the client's [`EcsReplicator`](crate::ecs_net::EcsReplicator) updates.
+    pub fn execute_net_command(&mut self, command: NetEcsCommand) -> Result<(), MigrationError> {
         match command {
-            NetEcsCommand::SetComponent(_, component) => {
+            NetEcsCommand::SetComponent(server_entity_id, component) => {
+                let component = self.migrations.migrate(component)?;
+                let component = self.map_server_entity_ids(component);
+                let entity_id = self.entity_id_from_server(server_entity_id);
                 self.set_component_boxed(entity_id, component);
             }
-            NetEcsCommand::DeleteComponent(_, component_id) => {
+            NetEcsCommand::SetComponentDelta(server_entity_id, delta) => {
+                let entity_id = self.entity_id_from_server(server_entity_id);
+                let component = self
+                    .get_component(entity_id, delta.component_id)
+                    .and_then(|base| base.as_serializable())
+                    .and_then(|base| delta.apply(base).ok());
+
+                match component {
+                    Some(component) => {
+                        let component = self.migrations.migrate(component)?;
+                        self.set_component_boxed(entity_id, component);
+                    }
+                    None => self
+                        .net_queues
+                        .outgoing_requests
+                        .push((server_entity_id, delta.component_id)),
+                }
+            }
+            NetEcsCommand::RequestComponent(..) | NetEcsCommand::Input(..) => {}
+            // only meaningful to a client's Prediction
+            NetEcsCommand::InputAck(..) => {}
+            NetEcsCommand::TransferOwnership(server_entity_id, owner, seq) => self
+                .net_queues
+                .ownership_transfers
+                .push((server_entity_id, owner, seq)),
+            NetEcsCommand::AcceptOwnership(..) => {}
+            NetEcsCommand::Rpc(server_entity_id, rpc) => {
+                let rpc = self.migrations.migrate(rpc)?;
+                let entity_id = self.entity_id_from_server(server_entity_id);
+                self.net_queues.incoming_rpcs.push((entity_id, rpc));
+            }
+            NetEcsCommand::DeleteComponent(server_entity_id, component_id) => {
+                let entity_id = self.entity_id_from_server(server_entity_id);
                 self.delete_component(entity_id, component_id);
             }
-            NetEcsCommand::DeleteEntity(_) => {
+            NetEcsCommand::SpawnEntity(server_entity_id, bundle) => {
+                let entity_id = self.entity_id_from_server(server_entity_id);
+                let components = bundle
+                    .into_serializable_components()
+                    .map(|component| self.migrations.migrate(component))
+                    .collect::<Result<Vec<_>, _>>()?;
+                for component in components {
+                    let component = self.map_server_entity_ids(component);
+                    self.set_component_boxed(entity_id, component);
+                }
+            }
+            NetEcsCommand::DeleteEntity(server_entity_id) => {
+                let entity_id = self.entity_id_from_server(server_entity_id);
                 self.delete_entity(entity_id);
             }
+            NetEcsCommand::Batch(commands) => {
+                // migrated up front so that a batch that can't be applied whole isn't applied at all
+                for command in self.migrate_net_commands(commands)? {
+                    self.execute_net_command(command)?;
+                }
+            }
+            NetEcsCommand::SetResource(resource) => {
+                let resource = self.migrations.migrate(resource)?;
+                self.insert_resource_boxed(resource);
+            }
+            NetEcsCommand::DeleteResource(component_id) => {
+                self.remove_resource_boxed(component_id);
+            }
         }
-    }
 
-    pub fn execute_client_net_command(&mut self, client_id: ClientId, command: NetEcsCommand) {
-        // clients cannot remove components or entities
-        if let NetEcsCommand::SetComponent(server_entity_id, component) = command {
-            let entity_id = server_entity_id.0;
+        Ok(())
+    }
 
-            // Replicate components are protected
-            if component.component_id() == Replicate::COMPONENT_ID {
-                return;
-            }
+    /// Migrates every component in some commands to its current version, failing without having changed anything if
+    /// any of them can't be. Only the result of a [`NetEcsCommand::SetComponentDelta`] is left to be migrated when it's
+    /// applied, and a delta is always made against a value that has been migrated already.
+    pub(crate) fn migrate_net_commands(
+        &self,
+        commands: Vec<NetEcsCommand>,
+    ) -> Result<Vec<NetEcsCommand>, MigrationError> {
+        commands
+            .into_iter()
+            .map(|command| {
+                Ok(match command {
+                    NetEcsCommand::SetComponent(server_entity_id, component) => {
+                        NetEcsCommand::SetComponent(
+                            server_entity_id,
+                            self.migrations.migrate(component)?,
+                        )
+                    }
+                    NetEcsCommand::SpawnEntity(server_entity_id, bundle) => {
+                        let mut migrated = SerializableComponentBundle::new();
+                        for component in bundle.into_serializable_components() {
+                            migrated.set_component_boxed(self.migrations.migrate(component)?);
+                        }
+                        NetEcsCommand::SpawnEntity(server_entity_id, migrated)
+                    }
+                    NetEcsCommand::Rpc(server_entity_id, rpc) => {
+                        NetEcsCommand::Rpc(server_entity_id, self.migrations.migrate(rpc)?)
+                    }
+                    NetEcsCommand::SetResource(resource) => {
+                        NetEcsCommand::SetResource(self.migrations.migrate(resource)?)
+                    }
+                    NetEcsCommand::Batch(commands) => {
+                        NetEcsCommand::Batch(self.migrate_net_commands(commands)?)
+                    }
+                    command => command,
+                })
+            })
+            .collect()
+    }
 
-            if let Some((replicate,)) = query_one!(self, entity_id, Replicate) {
-                // they need to own the entity and the component needs to be in the list of writable components
+    pub(crate) fn net_queues(&mut self) -> &mut NetQueues {
+        &mut self.net_queues
+    }
 
-                if replicate.owner != Some(client_id) {
-                    return;
-                }
-                if !replicate
-                    .client_writable
-                    .contains(&component.component_id())
-                {
-                    return;
+    /// Applies a command sent by a client, ignoring it if the client isn't allowed to make the change. Component writes
+    /// are checked by [`ClientWriteValidation`] first. Fails if a component in the command couldn't be migrated to its
+    /// current version.
+    pub fn execute_client_net_command(
+        &mut self,
+        client_id: ClientId,
+        command: NetEcsCommand,
+    ) -> Result<(), MigrationError> {
+        match command {
+            NetEcsCommand::RequestComponent(server_entity_id, component_id) => {
+                self.net_queues
+                    .incoming_requests
+                    .entry(client_id)
+                    .or_default()
+                    .push((server_entity_id, component_id));
+                return Ok(());
+            }
+            NetEcsCommand::Input(server_entity_id, input_tick, input) => {
+                let input = self.migrations.migrate(input)?;
+                if self.has_authority(client_id, server_entity_id.0) {
+                    self.net_queues
+                        .inputs
+                        .push((server_entity_id, input_tick, input));
                 }
-
-                self.set_component_boxed(entity_id, component);
+                return Ok(());
+            }
+            NetEcsCommand::AcceptOwnership(server_entity_id, seq) => {
+                self.accept_ownership(client_id, server_entity_id, seq);
+                return Ok(());
             }
+            _ => {}
         }
+
+        // clients cannot spawn or remove components or entities, touch resources, or batch commands, which would let
+        // them nest batches deep enough to overflow the stack
+        if let NetEcsCommand::SetComponent(server_entity_id, component) = command {
+            let component = self.migrations.migrate(component)?;
+            self.apply_client_write(client_id, server_entity_id, component);
+        }
+
+        Ok(())
     }
 
     pub fn get_component(
@@ -113,7 +469,10 @@ impl World {
         entity_id: EntityId,
         component_id: ComponentId,
     ) -> Option<&mut Box<dyn Component>> {
-        self.components.get_mut(&component_id)?.get_mut(entity_id)
+        let change_tick = self.change_tick;
+        self.components
+            .get_mut(&component_id)?
+            .get_mut(entity_id, change_tick)
     }
 
     pub fn get_all_components(
@@ -131,10 +490,11 @@ impl World {
         &mut self,
         entity_id: EntityId,
     ) -> impl Iterator<Item = (ComponentId, &mut Box<dyn Component>)> {
+        let change_tick = self.change_tick;
         self.components
             .iter_mut()
             .filter_map(move |(&component_id, component_set)| {
-                Some((component_id, component_set.get_mut(entity_id)?))
+                Some((component_id, component_set.get_mut(entity_id, change_tick)?))
             })
     }
 
@@ -166,11 +526,38 @@ impl World {
         None
     }
 
+    /// Sets a component, returning the old one. A [`Parent`] that would create a cycle is dropped without changing
+    /// anything, and `None` is returned.
     pub fn set_component_boxed(
         &mut self,
         entity_id: EntityId,
         component: Box<dyn Component>,
     ) -> Option<Box<dyn Component>> {
+        if let Some(&Parent(parent_id)) = component.downcast_ref::<Parent>() {
+            if self.would_create_cycle(entity_id, parent_id) {
+                return None;
+            }
+            self.attach_child(parent_id, entity_id);
+        }
+
+        let component_id = component.component_id();
+        let old_component = self.set_component_raw(entity_id, component);
+        if let Some(old_component) = &old_component {
+            self.run_replace_hooks(entity_id, old_component.as_ref());
+        } else {
+            self.run_insert_hooks(entity_id, component_id);
+        }
+
+        old_component
+    }
+
+    /// Sets a component without keeping the entity hierarchy consistent.
+    pub(crate) fn set_component_raw(
+        &mut self,
+        entity_id: EntityId,
+        component: Box<dyn Component>,
+    ) -> Option<Box<dyn Component>> {
+        let change_tick = self.change_tick;
         let component_set = if let Some(set) = self.components.get_mut(&component.component_id()) {
             set
         } else {
@@ -181,7 +568,25 @@ impl World {
             self.components.get_mut(&component.component_id())?
         };
 
-        component_set.set(entity_id, component)
+        let component_id = component.component_id();
+        let old_component = component_set.set(entity_id, component, change_tick);
+
+        if NameIndex::indexes(component_id) {
+            if let Some(old_component) = &old_component {
+                self.name_index
+                    .on_component_deleted(entity_id, old_component.as_ref());
+            }
+            if let Some(component) = self
+                .components
+                .get(&component_id)
+                .and_then(|component_set| component_set.get(entity_id))
+            {
+                self.name_index
+                    .on_component_set(entity_id, component.as_ref());
+            }
+        }
+
+        old_component
     }
 
     pub fn delete_component(
@@ -189,17 +594,146 @@ impl World {
         entity_id: EntityId,
         component_id: ComponentId,
     ) -> Option<Box<dyn Component>> {
-        self.components.get_mut(&component_id)?.delete(entity_id)
+        let component = self.delete_component_raw(entity_id, component_id)?;
+        self.on_hierarchy_component_deleted(entity_id, component.as_ref());
+        self.run_remove_hooks(entity_id, component.as_ref());
+        Some(component)
+    }
+
+    /// Deletes a component without keeping the entity hierarchy consistent.
+    pub(crate) fn delete_component_raw(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) -> Option<Box<dyn Component>> {
+        let component = self.components.get_mut(&component_id)?.delete(entity_id)?;
+        self.name_index
+            .on_component_deleted(entity_id, component.as_ref());
+        self.change_tracker
+            .on_component_deleted(entity_id, component.as_ref());
+        Some(component)
     }
 
     pub fn delete_entity(&mut self, entity_id: EntityId) -> bool {
-        let mut found = false;
-        for (_, component_set) in self.components.iter_mut() {
-            if component_set.delete(entity_id).is_some() {
-                found = true;
-            }
+        self.detach_from_hierarchy(entity_id);
+
+        let removed_components: Vec<Box<dyn Component>> = self
+            .components
+            .values_mut()
+            .filter_map(|component_set| component_set.delete(entity_id))
+            .collect();
+
+        if removed_components.is_empty() {
+            return false;
+        }
+
+        for component in removed_components {
+            self.name_index
+                .on_component_deleted(entity_id, component.as_ref());
+            self.change_tracker
+                .on_component_deleted(entity_id, component.as_ref());
+            self.run_remove_hooks(entity_id, component.as_ref());
+        }
+        self.run_despawn_hooks(entity_id);
+
+        true
+    }
+
+    /// Every entity that has at least one component.
+    pub fn entities(&self) -> impl Iterator<Item = EntityId> {
+        self.query([], []).map(|(entity_id, [])| entity_id)
+    }
+
+    /// Deletes every entity and resource in the world.
+    pub fn clear(&mut self) {
+        let entity_ids: Vec<EntityId> = self
+            .query([], [])
+            .map(|(entity_id, [])| entity_id)
+            .collect();
+        for entity_id in entity_ids {
+            self.delete_entity(entity_id);
         }
-        found
+
+        self.resources.clear();
+        self.server_entity_id_map.clear();
+    }
+
+    pub fn has_resource(&self, component_id: ComponentId) -> bool {
+        self.resources.contains_key(&component_id)
+    }
+
+    pub fn get_resource<T: ComponentType>(&self) -> Option<&T> {
+        self.resources.get(&T::COMPONENT_ID)?.downcast_ref()
+    }
+
+    pub fn get_resource_mut<T: ComponentType>(&mut self) -> Option<&mut T> {
+        (self.resources.get_mut(&T::COMPONENT_ID)?.as_mut() as &mut dyn Any).downcast_mut()
+    }
+
+    pub fn get_resource_boxed(&self, component_id: ComponentId) -> Option<&Box<dyn Component>> {
+        self.resources.get(&component_id)
+    }
+
+    pub fn get_resource_boxed_mut(
+        &mut self,
+        component_id: ComponentId,
+    ) -> Option<&mut Box<dyn Component>> {
+        self.resources.get_mut(&component_id)
+    }
+
+    pub fn resources(&self) -> impl Iterator<Item = (ComponentId, &Box<dyn Component>)> {
+        self.resources
+            .iter()
+            .map(|(&component_id, resource)| (component_id, resource))
+    }
+
+    pub fn serializable_resources(
+        &self,
+    ) -> impl Iterator<Item = (ComponentId, &dyn SerializableComponent)> {
+        self.resources().filter_map(|(component_id, resource)| {
+            Some((component_id, resource.as_serializable()?))
+        })
+    }
+
+    pub fn insert_resource<T: ComponentType>(&mut self, resource: T) -> Option<T> {
+        if let Some(old_resource) = self.insert_resource_boxed(Box::new(resource)) {
+            return Some(*Box::<dyn Any + 'static>::downcast::<T>(old_resource).ok()?);
+        }
+
+        None
+    }
+
+    pub fn insert_resource_boxed(
+        &mut self,
+        resource: Box<dyn Component>,
+    ) -> Option<Box<dyn Component>> {
+        self.resources.insert(resource.component_id(), resource)
+    }
+
+    pub fn remove_resource<T: ComponentType>(&mut self) -> Option<T> {
+        let resource = self.remove_resource_boxed(T::COMPONENT_ID)?;
+        Some(*Box::<dyn Any + 'static>::downcast::<T>(resource).ok()?)
+    }
+
+    pub fn remove_resource_boxed(
+        &mut self,
+        component_id: ComponentId,
+    ) -> Option<Box<dyn Component>> {
+        self.resources.remove(&component_id)
+    }
+
+    /// Temporarily takes the `T` resource out of the world so that it can be mutated alongside the rest of the world,
+    /// e.g. while iterating over [`query_mut`]. Returns `None` without calling `f` if the resource doesn't exist.
+    ///
+    /// The resource is not visible from the world while `f` runs.
+    pub fn resource_scope<T: ComponentType, R>(
+        &mut self,
+        f: impl FnOnce(&mut World, &mut T) -> R,
+    ) -> Option<R> {
+        let mut resource = self.remove_resource::<T>()?;
+        let result = f(self, &mut resource);
+        self.insert_resource(resource);
+        Some(result)
     }
 
     fn required_iter_upper_bound(&self, with: &[ComponentId]) -> usize {
@@ -230,126 +764,127 @@ impl World {
         with: [ComponentId; WITH],
         without: [ComponentId; WITHOUT],
     ) -> Option<[&Box<dyn Component>; WITH]> {
-        if with.is_empty() && !self.has_entity(entity_id) {
-            return None;
-        }
-
-        for &excluded_component_id in without.iter() {
-            if self.has_component(entity_id, excluded_component_id) {
-                return None;
-            }
-        }
-
-        let mut component_slots: [Option<&Box<dyn Component>>; WITH] = array::from_fn(|_| None);
-        for (index, slot) in component_slots.iter_mut().enumerate() {
-            *slot = Some(self.get_component(entity_id, with[index])?)
-        }
-
-        Some(array::from_fn(|index| component_slots[index].unwrap()))
+        self.query_at(entity_id, with, without)
     }
 
+    /// Like [`World::query_one`], but mutable. Marks the components as changed if the entity matches.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `with` contains the same component twice.
     pub fn query_one_mut<const WITH: usize, const WITHOUT: usize>(
         &mut self,
         entity_id: EntityId,
         with: [ComponentId; WITH],
         without: [ComponentId; WITHOUT],
     ) -> Option<[&mut Box<dyn Component>; WITH]> {
-        if with.is_empty() && !self.has_entity(entity_id) {
-            return None;
-        }
+        self.query_at_mut(entity_id, with, without)
+    }
 
-        for &excluded_component_id in without.iter() {
-            if self.has_component(entity_id, excluded_component_id) {
-                return None;
-            }
-        }
+    pub fn query<const WITH: usize, const WITHOUT: usize>(
+        &self,
+        with: [ComponentId; WITH],
+        without: [ComponentId; WITHOUT],
+    ) -> impl Iterator<Item = (EntityId, [&Box<dyn Component>; WITH])> {
+        self.query_filtered(with, without, [], Tick::default())
+    }
 
-        let mut component_slots: [Option<&mut Box<dyn Component>>; WITH] = array::from_fn(|_| None);
-        for (index, slot) in component_slots.iter_mut().enumerate() {
-            // ew
-            unsafe {
-                *slot = Some(
-                    ((self.get_component(entity_id, with[index])?) as *const Box<dyn Component>
-                        as *mut Box<dyn Component>)
-                        .as_mut()?,
-                )
-            }
-        }
+    pub fn query_mut<const WITH: usize, const WITHOUT: usize>(
+        &mut self,
+        with: [ComponentId; WITH],
+        without: [ComponentId; WITHOUT],
+    ) -> impl Iterator<Item = (EntityId, [&mut Box<dyn Component>; WITH])> {
+        self.query_mut_filtered(with, without, [], Tick::default())
+    }
 
-        Some(array::from_fn(|index| {
-            component_slots[index].take().unwrap()
-        }))
+    fn passes_filters(&self, entity_id: EntityId, filters: &[QueryFilter], since: Tick) -> bool {
+        filters.iter().all(|filter| {
+            self.component_ticks(entity_id, filter.component_id())
+                .is_some_and(|ticks| filter.matches(ticks, since))
+        })
     }
 
-    pub fn query<const WITH: usize, const WITHOUT: usize>(
+    /// Like [`World::query`], but only matches entities that pass every filter, where `since` is the tick that the
+    /// caller last checked for changes.
+    pub fn query_filtered<const WITH: usize, const WITHOUT: usize, const FILTERS: usize>(
         &self,
         with: [ComponentId; WITH],
         without: [ComponentId; WITHOUT],
+        filters: [QueryFilter; FILTERS],
+        since: Tick,
     ) -> impl Iterator<Item = (EntityId, [&Box<dyn Component>; WITH])> {
         let upper_bound = self.required_iter_upper_bound(&with);
         (0..upper_bound).filter_map(move |i| {
             let entity_id = i.into();
 
+            if !self.passes_filters(entity_id, &filters, since) {
+                return None;
+            }
+
             Some((entity_id, self.query_one(entity_id, with, without)?))
         })
     }
 
-    pub fn query_mut<const WITH: usize, const WITHOUT: usize>(
+    /// Like [`World::query_mut`], but only matches entities that pass every filter, where `since` is the tick that
+    /// the caller last checked for changes. Only matching entities are marked as changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `with` contains the same component twice.
+    pub fn query_mut_filtered<const WITH: usize, const WITHOUT: usize, const FILTERS: usize>(
         &mut self,
         with: [ComponentId; WITH],
         without: [ComponentId; WITHOUT],
+        filters: [QueryFilter; FILTERS],
+        since: Tick,
     ) -> impl Iterator<Item = (EntityId, [&mut Box<dyn Component>; WITH])> {
+        assert_distinct(&with);
         let upper_bound = self.required_iter_upper_bound(&with);
+        let world: *mut Self = self;
 
+        // every entity is visited once, so the components of different items never alias
         (0..upper_bound).filter_map(move |i| {
             let entity_id = i.into();
+            let world = unsafe { &mut *world };
 
-            if with.is_empty() && !self.has_entity(entity_id) {
+            if !world.passes_filters(entity_id, &filters, since) {
                 return None;
             }
 
-            for &excluded_component_id in without.iter() {
-                if self.has_component(entity_id, excluded_component_id) {
-                    return None;
-                }
-            }
-
-            let mut component_slots: [Option<&mut Box<dyn Component>>; WITH] =
-                array::from_fn(|_| None);
-            for (index, slot) in component_slots.iter_mut().enumerate() {
-                // ew
-                // is there an easier way to force an immutable reference to be mutable?
-                unsafe {
-                    *slot = Some(
-                        ((self.get_component(entity_id, with[index])?) as *const Box<dyn Component>
-                            as *mut Box<dyn Component>)
-                            .as_mut()?,
-                    )
-                }
-            }
-
-            Some((
-                entity_id,
-                array::from_fn(|index| component_slots[index].take().unwrap()),
-            ))
+            Some((entity_id, world.query_at_mut(entity_id, with, without)?))
         })
     }
 
-    pub fn update_change_tracker(&self) {
+    /// Sends every change to a tracked component since the last update, then advances the change tick.
+    pub fn update_change_tracker(&mut self) {
         self.change_tracker.update(self);
+        self.increment_change_tick();
     }
 
-    pub fn update_entity_change_tracker(&self, entity_id: EntityId) {
+    pub fn update_entity_change_tracker(&mut self, entity_id: EntityId) {
         self.change_tracker.update_entity(self, entity_id);
+        self.increment_change_tick();
     }
 
     pub fn update_entity_component_change_tracker(
-        &self,
+        &mut self,
         entity_id: EntityId,
         component_id: ComponentId,
     ) {
         self.change_tracker
             .update_entity_component(self, entity_id, component_id);
+        self.increment_change_tick();
+    }
+
+    /// Receives whether a component of an entity was added, changed or removed, for components of any type. Sent by
+    /// [`World::update_change_tracker`].
+    pub fn component_changes(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) -> EventReceiver<ComponentChange> {
+        self.change_tracker
+            .subscribe_changes(entity_id, component_id)
     }
 
     /// # Safety
@@ -384,6 +919,74 @@ impl World {
     }
 }
 
+/// Mutable access marks components as changed, like [`World::get_component_mut`].
+impl ComponentStorage for World {
+    type Key = EntityId;
+    type Stored = Box<dyn Component>;
+
+    fn component_keys(&self) -> Vec<EntityId> {
+        self.entities().collect()
+    }
+
+    fn contains_key(&self, entity_id: EntityId) -> bool {
+        self.has_entity(entity_id)
+    }
+
+    fn component_at(
+        &self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) -> Option<&Box<dyn Component>> {
+        self.get_component(entity_id, component_id)
+    }
+
+    /// Marks the component as changed, like [`World::get_component_mut`].
+    fn component_at_mut(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+    ) -> Option<&mut Box<dyn Component>> {
+        self.get_component_mut(entity_id, component_id)
+    }
+
+    fn has_component_at(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        self.has_component(entity_id, component_id)
+    }
+}
+
+/// A fluent builder for a new entity, created with [`World::build_entity`]. Components are inserted into the world
+/// immediately.
+#[derive(Debug)]
+pub struct EntityBuilder<'a> {
+    world: &'a mut World,
+    entity_id: EntityId,
+}
+
+impl EntityBuilder<'_> {
+    pub fn id(&self) -> EntityId {
+        self.entity_id
+    }
+
+    pub fn with<T: Component>(self, component: T) -> Self {
+        self.world.set_component(self.entity_id, component);
+        self
+    }
+
+    pub fn with_boxed(self, component: Box<dyn Component>) -> Self {
+        self.world.set_component_boxed(self.entity_id, component);
+        self
+    }
+
+    pub fn with_bundle(self, bundle: impl Bundle) -> Self {
+        self.world.insert_bundle(self.entity_id, bundle);
+        self
+    }
+
+    pub fn build(self) -> EntityId {
+        self.entity_id
+    }
+}
+
 #[macro_export]
 macro_rules! query {
     ($world:expr, ($($with:ty),*), ($($without:ty),*)) => {
@@ -412,6 +1015,38 @@ macro_rules! query_mut {
     };
 }
 
+#[macro_export]
+macro_rules! query_filtered {
+    ($world:expr, $since:expr, ($($with:ty),*), ($($without:ty),*), ($($filter:ty),*)) => {
+        ::paste::paste! {
+            $world.query_filtered(
+                [$(<$with>::COMPONENT_ID),*],
+                [$(<$without>::COMPONENT_ID),*],
+                [$(<$filter as hydrogen::ecs::change_detection::ChangeFilter>::FILTER),*],
+                $since,
+            ).map(|(entity_id, [$([<$with:snake>]),*])| {
+                unsafe { (entity_id, ($(([<$with:snake>] as *const ::std::boxed::Box<dyn hydrogen::ecs::component::Component> as *const ::std::boxed::Box<$with>).as_ref().unwrap().as_ref(),)*)) }
+            })
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! query_mut_filtered {
+    ($world:expr, $since:expr, ($($with:ty),*), ($($without:ty),*), ($($filter:ty),*)) => {
+        ::paste::paste! {
+            $world.query_mut_filtered(
+                [$(<$with>::COMPONENT_ID),*],
+                [$(<$without>::COMPONENT_ID),*],
+                [$(<$filter as hydrogen::ecs::change_detection::ChangeFilter>::FILTER),*],
+                $since,
+            ).map(|(entity_id, [$([<$with:snake>]),*])| {
+                unsafe { (entity_id, ($(([<$with:snake>] as *const ::std::boxed::Box<dyn hydrogen::ecs::component::Component> as *mut ::std::boxed::Box<$with>).as_mut().unwrap().as_mut(),)*)) }
+            })
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! query_one {
     ($world:expr, $entity_id:expr, ($($with:ty),*), ($($without:ty),*)) => {
@@ -452,4 +1087,7 @@ macro_rules! get_component_changed_event_sender {
     };
 }
 
-pub use {get_component_changed_event_sender, query, query_mut, query_one, query_one_mut};
+pub use {
+    get_component_changed_event_sender, query, query_filtered, query_mut, query_mut_filtered,
+    query_one, query_one_mut,
+};
@@ -1,8 +1,62 @@
 use const_fnv1a_hash::fnv1a_hash_str_64;
 use quote::quote;
-use syn::{DeriveInput, parse_macro_input};
+use syn::{Data, DeriveInput, Fields, Index, LitInt, LitStr, parse_macro_input};
 
-fn common_component(input: &DeriveInput) -> proc_macro2::TokenStream {
+/// The settings given by a `#[component(...)]` attribute.
+struct ComponentAttributes {
+    /// The stable identifier that the `ComponentId` is derived from. Defaults to the type's name.
+    id: String,
+    version: u32,
+}
+
+impl ComponentAttributes {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        let mut attributes = Self {
+            id: input.ident.to_string(),
+            version: 0,
+        };
+
+        for attr in input.attrs.iter() {
+            if !attr.path().is_ident("component") {
+                continue;
+            }
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("id") {
+                    attributes.id = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("version") {
+                    attributes.version = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `id` or `version`"))
+                }
+            })?;
+        }
+
+        Ok(attributes)
+    }
+
+    fn component_id(&self) -> u64 {
+        fnv1a_hash_str_64(&self.id)
+    }
+
+    /// The name that the component is tagged with when serialized. Each version gets its own tag so that payloads
+    /// from older versions can still be deserialized into the old type and then migrated.
+    fn serialized_name(&self) -> String {
+        if self.version == 0 {
+            self.id.clone()
+        } else {
+            format!("{}@{}", self.id, self.version)
+        }
+    }
+}
+
+fn common_component(
+    input: &DeriveInput,
+    attributes: &ComponentAttributes,
+    serializable: bool,
+) -> proc_macro2::TokenStream {
     let DeriveInput {
         attrs: _,
         vis: _,
@@ -12,14 +66,33 @@ fn common_component(input: &DeriveInput) -> proc_macro2::TokenStream {
     } = input;
 
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
-    let component_id = fnv1a_hash_str_64(&ident.to_string());
+    let component_id = attributes.component_id();
+    let component_version = attributes.version;
     let display_name = ident.to_string();
 
+    // generic components can't be registered since there's no single concrete type to register
+    let registration = generics.params.is_empty().then(|| {
+        quote! {
+            hydrogen::ecs::registry::inventory::submit! {
+                hydrogen::ecs::registry::ComponentRegistration {
+                    component_id: hydrogen::ecs::component::ComponentId(#component_id),
+                    version: #component_version,
+                    display_name: #display_name,
+                    type_path: concat!(module_path!(), "::", #display_name),
+                    serializable: #serializable,
+                    type_id: || std::any::TypeId::of::<#ident>(),
+                }
+            }
+        }
+    });
+
     quote! {
+        #registration
+
         impl #impl_generics #ident #ty_generics #where_clause {
             pub const COMPONENT_ID: hydrogen::ecs::component::ComponentId = hydrogen::ecs::component::ComponentId(#component_id);
+            pub const COMPONENT_VERSION: u32 = #component_version;
             pub const DISPLAY_NAME: &'static str = #display_name;
-
             pub fn query_one(
                 ecs: &hydrogen::ecs::world::World,
                 entity_id: hydrogen::ecs::entity::EntityId,
@@ -42,13 +115,23 @@ fn common_component(input: &DeriveInput) -> proc_macro2::TokenStream {
                 hydrogen::ecs::query_mut!(ecs, #ident).map(|(e, (c,))| (e, c))
             }
         }
+
+        impl #impl_generics hydrogen::ecs::component::ComponentType for #ident #ty_generics #where_clause {
+            const COMPONENT_ID: hydrogen::ecs::component::ComponentId = hydrogen::ecs::component::ComponentId(#component_id);
+            const COMPONENT_VERSION: u32 = #component_version;
+            const DISPLAY_NAME: &'static str = #display_name;
+        }
     }
 }
 
-#[proc_macro_derive(Component)]
+#[proc_macro_derive(Component, attributes(component))]
 pub fn component(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let common = common_component(&input);
+    let attributes = match ComponentAttributes::parse(&input) {
+        Ok(attributes) => attributes,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let common = common_component(&input, &attributes, false);
 
     let DeriveInput {
         attrs: _,
@@ -59,7 +142,8 @@ pub fn component(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     } = input;
 
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
-    let component_id = fnv1a_hash_str_64(&ident.to_string());
+    let component_id = attributes.component_id();
+    let component_version = attributes.version;
     let display_name = ident.to_string();
 
     quote! {
@@ -69,6 +153,9 @@ pub fn component(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             fn component_id(&self) -> hydrogen::ecs::component::ComponentId {
                 hydrogen::ecs::component::ComponentId(#component_id)
             }
+            fn component_version(&self) -> u32 {
+                #component_version
+            }
             fn display_name(&self) -> &'static str {
                 #display_name
             }
@@ -88,10 +175,14 @@ pub fn component(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     }.into()
 }
 
-#[proc_macro_derive(SerializableComponent)]
+#[proc_macro_derive(SerializableComponent, attributes(component))]
 pub fn serializable_component(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let common = common_component(&input);
+    let attributes = match ComponentAttributes::parse(&input) {
+        Ok(attributes) => attributes,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let common = common_component(&input, &attributes, true);
 
     let DeriveInput {
         attrs: _,
@@ -102,8 +193,10 @@ pub fn serializable_component(input: proc_macro::TokenStream) -> proc_macro::Tok
     } = input;
 
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
-    let component_id = fnv1a_hash_str_64(&ident.to_string());
+    let component_id = attributes.component_id();
+    let component_version = attributes.version;
     let display_name = ident.to_string();
+    let serialized_name = attributes.serialized_name();
 
     quote! {
         #common
@@ -112,6 +205,9 @@ pub fn serializable_component(input: proc_macro::TokenStream) -> proc_macro::Tok
             fn component_id(&self) -> hydrogen::ecs::component::ComponentId {
                 hydrogen::ecs::component::ComponentId(#component_id)
             }
+            fn component_version(&self) -> u32 {
+                #component_version
+            }
             fn display_name(&self) -> &'static str {
                 #display_name
             }
@@ -129,7 +225,7 @@ pub fn serializable_component(input: proc_macro::TokenStream) -> proc_macro::Tok
             }
         }
 
-        #[typetag::serde]
+        #[typetag::serde(name = #serialized_name)]
         impl #impl_generics hydrogen::ecs::component::SerializableComponent for #ident #ty_generics #where_clause {
             fn clone_box(&self) -> Box<dyn hydrogen::ecs::component::SerializableComponent> {
                 Box::new(self.clone())
@@ -137,3 +233,150 @@ pub fn serializable_component(input: proc_macro::TokenStream) -> proc_macro::Tok
         }
     }.into()
 }
+
+#[proc_macro_derive(Bundle)]
+pub fn bundle(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let DeriveInput {
+        attrs: _,
+        vis: _,
+        ident,
+        generics,
+        data,
+    } = parse_macro_input!(input as DeriveInput);
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let Data::Struct(data_struct) = data else {
+        return syn::Error::new(ident.span(), "Bundle can only be derived for structs")
+            .to_compile_error()
+            .into();
+    };
+
+    let field_accessors: Vec<proc_macro2::TokenStream> = match data_struct.fields {
+        Fields::Named(fields) => fields
+            .named
+            .into_iter()
+            .map(|field| {
+                let field_ident = field.ident.unwrap();
+                quote! { #field_ident }
+            })
+            .collect(),
+        Fields::Unnamed(fields) => (0..fields.unnamed.len())
+            .map(|index| {
+                let index = Index::from(index);
+                quote! { #index }
+            })
+            .collect(),
+        Fields::Unit => vec![],
+    };
+
+    quote! {
+        impl #impl_generics hydrogen::ecs::component::Bundle for #ident #ty_generics #where_clause {
+            fn into_components(self) -> Vec<Box<dyn hydrogen::ecs::component::Component>> {
+                vec![#(Box::new(self.#field_accessors)),*]
+            }
+        }
+    }
+    .into()
+}
+
+#[proc_macro_derive(Reflect, attributes(reflect))]
+pub fn reflect(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let DeriveInput {
+        attrs: _,
+        vis: _,
+        ident,
+        generics,
+        data,
+    } = parse_macro_input!(input as DeriveInput);
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let display_name = ident.to_string();
+
+    let Data::Struct(data_struct) = data else {
+        return syn::Error::new(ident.span(), "Reflect can only be derived for structs")
+            .to_compile_error()
+            .into();
+    };
+
+    let mut field_names = Vec::new();
+    let mut field_accessors = Vec::new();
+    let mut field_types = Vec::new();
+    for (index, field) in data_struct.fields.into_iter().enumerate() {
+        let mut skip = false;
+        for attr in field.attrs.iter() {
+            if !attr.path().is_ident("reflect") {
+                continue;
+            }
+
+            let result = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `skip`"))
+                }
+            });
+            if let Err(e) = result {
+                return e.to_compile_error().into();
+            }
+        }
+        if skip {
+            continue;
+        }
+
+        match field.ident {
+            Some(field_ident) => {
+                field_names.push(field_ident.to_string());
+                field_accessors.push(quote! { #field_ident });
+            }
+            None => {
+                field_names.push(index.to_string());
+                let index = Index::from(index);
+                field_accessors.push(quote! { #index });
+            }
+        }
+        field_types.push(field.ty);
+    }
+
+    // generic types can't be registered since there's no single concrete type to register
+    let registration = generics.params.is_empty().then(|| {
+        quote! {
+            hydrogen::ecs::registry::inventory::submit! {
+                hydrogen::ecs::reflect::ReflectRegistration::of::<#ident>()
+            }
+        }
+    });
+
+    quote! {
+        #registration
+
+        impl #impl_generics hydrogen::ecs::reflect::Reflect for #ident #ty_generics #where_clause {
+            fn type_name(&self) -> &'static str {
+                #display_name
+            }
+
+            fn fields(&self) -> Vec<hydrogen::ecs::reflect::FieldInfo> {
+                vec![#(hydrogen::ecs::reflect::FieldInfo {
+                    name: #field_names.to_string(),
+                    type_name: std::any::type_name::<#field_types>(),
+                }),*]
+            }
+
+            fn field(&self, name: &str) -> Option<&dyn hydrogen::ecs::reflect::Reflect> {
+                match name {
+                    #(#field_names => Some(&self.#field_accessors),)*
+                    _ => None,
+                }
+            }
+
+            fn field_mut(&mut self, name: &str) -> Option<&mut dyn hydrogen::ecs::reflect::Reflect> {
+                match name {
+                    #(#field_names => Some(&mut self.#field_accessors),)*
+                    _ => None,
+                }
+            }
+        }
+    }
+    .into()
+}